@@ -0,0 +1,164 @@
+use crate::vm_value::Value;
+
+//This file defines the bytecode format executed by the VM.
+//Each instruction is a single opcode byte followed by its operand bytes (if any).
+//Operands wider than one byte are stored big-endian.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u8)]
+pub enum OpCode {
+    //u16 constant index
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    //u8 stack slot relative to the current frame
+    GetLocal,
+    SetLocal,
+    //u16 constant index of the variable name
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    //u8 index into the current closure's upvalues
+    GetUpvalue,
+    SetUpvalue,
+    //u16 constant index of the property name
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Not,
+    Negate,
+    Print,
+    //u16 forward offset
+    Jump,
+    JumpIfFalse,
+    //u16 backward offset
+    Loop,
+    //u8 argument count
+    Call,
+    //u16 constant index of the method name, u8 argument count
+    Invoke,
+    SuperInvoke,
+    //u16 constant index of the function, followed by an (is_local: u8, index: u8) pair per upvalue
+    Closure,
+    CloseUpvalue,
+    Return,
+    //u16 constant index of the class name
+    Class,
+    Inherit,
+    //u16 constant index of the method name
+    Method,
+}
+
+//Lookup table used to decode a byte back into an OpCode. Must be kept in declaration order
+const OPCODES: [OpCode; OpCode::Method as usize + 1] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::GetProperty,
+    OpCode::SetProperty,
+    OpCode::GetSuper,
+    OpCode::Equal,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Modulo,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::Invoke,
+    OpCode::SuperInvoke,
+    OpCode::Closure,
+    OpCode::CloseUpvalue,
+    OpCode::Return,
+    OpCode::Class,
+    OpCode::Inherit,
+    OpCode::Method,
+];
+
+impl From<u8> for OpCode {
+    fn from(byte: u8) -> Self {
+        OPCODES[byte as usize]
+    }
+}
+
+//A chunk is the compiled form of a single function body
+pub struct Chunk {
+    pub code: Vec<u8>,
+    //lines[i] is the source line of the instruction byte code[i]
+    pub lines: Vec<u32>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk {
+            code: Vec::new(),
+            lines: Vec::new(),
+            constants: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, byte: u8, line: u32) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: u32) {
+        self.write(op as u8, line);
+    }
+
+    pub fn write_u16(&mut self, value: u16, line: u32) {
+        self.write((value >> 8) as u8, line);
+        self.write((value & 0xff) as u8, line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        ((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16
+    }
+
+    //Returns the index of the constant. Equal strings and numbers share a slot
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        let existing = self.constants.iter().position(|constant| match (constant, &value) {
+            (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits(),
+            (Value::LoxString(a), Value::LoxString(b)) => a == b,
+            _ => false,
+        });
+
+        match existing {
+            Some(index) => index,
+            None => {
+                self.constants.push(value);
+                self.constants.len() - 1
+            }
+        }
+    }
+}
@@ -0,0 +1,791 @@
+use crate::chunk::{Chunk, OpCode};
+use crate::expr::{self, Expr};
+use crate::interpreter::RuntimeError;
+use crate::lox_type::LoxValue;
+use crate::stmt::{self, Stmt};
+use crate::token::{Token, TokenType::*};
+use crate::vm_value::{FunctionProto, Value};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+//This file compiles the parsed AST into bytecode for the VM.
+//It walks the tree with the same visitor traits as the Interpreter, so all compiler state lives in RefCells.
+//Static errors (e.g. reading a local in its own initializer) have already been reported by the Resolver,
+//so the compiler only needs to report the limits of the bytecode format.
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: String,
+    //None while the variable's initializer is being compiled
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+//Jumps emitted by break and continue statements, patched once the loop has been compiled
+struct LoopState {
+    //Scope depth outside the loop body. Locals deeper than this are discarded by break and continue
+    scope_depth: usize,
+    break_jumps: Vec<usize>,
+    continue_jumps: Vec<usize>,
+}
+
+//Compilation state of a single function. Nested function declarations push a new state
+struct FunctionState {
+    proto: FunctionProto,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<LoopState>,
+}
+
+//Where a named variable lives, determined at compile time
+enum VariableSlot {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+pub struct Compiler {
+    states: RefCell<Vec<FunctionState>>,
+    //Line of the most recently visited token. Used for nodes that do not store a token themselves
+    line: Cell<u32>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            states: RefCell::new(Vec::new()),
+            line: Cell::new(1),
+        }
+    }
+
+    //Compiles a whole program into the function that the VM runs as its top-level script
+    pub fn compile(self, statements: &[Rc<Stmt>]) -> Result<Rc<FunctionProto>, RuntimeError> {
+        self.begin_function("script".to_string(), FunctionKind::Script);
+        for (i, statement) in statements.iter().enumerate() {
+            match statement.as_ref() {
+                //The value of a trailing expression statement is the result of the script
+                Stmt::Expression { expression } if i + 1 == statements.len() => {
+                    self.compile_expr(expression.clone())?;
+                    self.emit_op(OpCode::Return);
+                }
+                _ => self.compile_stmt(statement.clone())?,
+            }
+        }
+        let (script, _upvalues) = self.end_function();
+        Ok(Rc::new(script))
+    }
+
+    fn compile_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+        statement.accept(self)
+    }
+
+    fn compile_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+        expr.accept(self)
+    }
+
+    fn error(&self, message: &str) -> RuntimeError {
+        RuntimeError::new(message.to_string(), self.line.get())
+    }
+
+    fn set_line(&self, token: &Token) {
+        self.line.set(token.line);
+    }
+
+    //Helper to reduce long state access chaining
+    fn with_chunk<R>(&self, f: impl FnOnce(&mut Chunk) -> R) -> R {
+        f(&mut self.states.borrow_mut().last_mut().unwrap().proto.chunk)
+    }
+
+    fn current_kind(&self) -> FunctionKind {
+        self.states.borrow().last().unwrap().kind
+    }
+
+    fn scope_depth(&self) -> usize {
+        self.states.borrow().last().unwrap().scope_depth
+    }
+
+    fn emit_byte(&self, byte: u8) {
+        let line = self.line.get();
+        self.with_chunk(|chunk| chunk.write(byte, line));
+    }
+
+    fn emit_op(&self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_op_u16(&self, op: OpCode, operand: u16) {
+        let line = self.line.get();
+        self.with_chunk(|chunk| {
+            chunk.write_op(op, line);
+            chunk.write_u16(operand, line);
+        });
+    }
+
+    fn emit_return(&self) {
+        if self.current_kind() == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+
+    fn make_constant(&self, value: Value) -> Result<u16, RuntimeError> {
+        let index = self.with_chunk(|chunk| chunk.add_constant(value));
+        if index > u16::MAX as usize {
+            return Err(self.error("Too many constants in one chunk."));
+        }
+        Ok(index as u16)
+    }
+
+    fn identifier_constant(&self, name: &str) -> Result<u16, RuntimeError> {
+        self.make_constant(Value::LoxString(Rc::from(name)))
+    }
+
+    //Emits a jump with a placeholder offset and returns the location of the offset for patching
+    fn emit_jump(&self, op: OpCode) -> usize {
+        self.emit_op_u16(op, u16::MAX);
+        self.with_chunk(|chunk| chunk.code.len() - 2)
+    }
+
+    fn patch_jump(&self, offset: usize) -> Result<(), RuntimeError> {
+        let jump = self.with_chunk(|chunk| chunk.code.len()) - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err(self.error("Too much code to jump over."));
+        }
+        self.with_chunk(|chunk| {
+            chunk.code[offset] = (jump >> 8) as u8;
+            chunk.code[offset + 1] = (jump & 0xff) as u8;
+        });
+        Ok(())
+    }
+
+    fn emit_loop(&self, loop_start: usize) -> Result<(), RuntimeError> {
+        //+3 skips over the Loop instruction itself
+        let offset = self.with_chunk(|chunk| chunk.code.len()) - loop_start + 3;
+        if offset > u16::MAX as usize {
+            return Err(self.error("Loop body too large."));
+        }
+        self.emit_op_u16(OpCode::Loop, offset as u16);
+        Ok(())
+    }
+
+    fn begin_function(&self, name: String, kind: FunctionKind) {
+        //Slot 0 holds the function being called, or the receiver for methods
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            _ => "",
+        };
+        self.states.borrow_mut().push(FunctionState {
+            proto: FunctionProto {
+                name,
+                arity: 0,
+                upvalue_count: 0,
+                chunk: Chunk::new(),
+            },
+            kind,
+            locals: vec![Local {
+                name: slot_zero.to_string(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            loops: Vec::new(),
+        });
+    }
+
+    fn end_function(&self) -> (FunctionProto, Vec<UpvalueRef>) {
+        self.emit_return();
+        let mut state = self.states.borrow_mut().pop().unwrap();
+        state.proto.upvalue_count = state.upvalues.len();
+        (state.proto, state.upvalues)
+    }
+
+    fn begin_scope(&self) {
+        self.states.borrow_mut().last_mut().unwrap().scope_depth += 1;
+    }
+
+    fn end_scope(&self) {
+        let mut states = self.states.borrow_mut();
+        let state = states.last_mut().unwrap();
+        state.scope_depth -= 1;
+
+        let line = self.line.get();
+        while let Some(local) = state.locals.last() {
+            match local.depth {
+                Some(depth) if depth <= state.scope_depth => break,
+                _ => (),
+            }
+            //Captured variables are moved off the stack and into their upvalue
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            state.proto.chunk.write_op(op, line);
+            state.locals.pop();
+        }
+    }
+
+    //Emits the instructions that discard the locals of the innermost loop's body without ending their scopes.
+    //Used by break and continue, which jump out of those scopes
+    fn discard_loop_locals(&self) {
+        let mut states = self.states.borrow_mut();
+        let state = states.last_mut().unwrap();
+        let loop_depth = state.loops.last().unwrap().scope_depth;
+
+        let line = self.line.get();
+        for local in state.locals.iter().rev() {
+            match local.depth {
+                Some(depth) if depth <= loop_depth => break,
+                _ => (),
+            }
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            state.proto.chunk.write_op(op, line);
+        }
+    }
+
+    fn add_local(&self, name: &str) -> Result<(), RuntimeError> {
+        let mut states = self.states.borrow_mut();
+        let state = states.last_mut().unwrap();
+        if state.locals.len() > u8::MAX as usize {
+            return Err(self.error("Too many local variables in function."));
+        }
+        state.locals.push(Local {
+            name: name.to_string(),
+            depth: None,
+            is_captured: false,
+        });
+        Ok(())
+    }
+
+    //Globals are late bound, so only locals need to be declared
+    fn declare_variable(&self, name: &Token) -> Result<(), RuntimeError> {
+        self.set_line(name);
+        if self.scope_depth() == 0 {
+            return Ok(());
+        }
+        self.add_local(&name.lexeme)
+    }
+
+    fn mark_initialized(&self) {
+        let mut states = self.states.borrow_mut();
+        let state = states.last_mut().unwrap();
+        if state.scope_depth == 0 {
+            return;
+        }
+        state.locals.last_mut().unwrap().depth = Some(state.scope_depth);
+    }
+
+    //Returns the constant holding the variable's name if it is a global
+    fn variable_constant(&self, name: &Token) -> Result<u16, RuntimeError> {
+        if self.scope_depth() > 0 {
+            return Ok(0);
+        }
+        self.identifier_constant(&name.lexeme)
+    }
+
+    fn define_variable(&self, global: u16) {
+        if self.scope_depth() > 0 {
+            self.mark_initialized();
+        } else {
+            self.emit_op_u16(OpCode::DefineGlobal, global);
+        }
+    }
+
+    fn resolve_local(&self, state_index: usize, name: &str) -> Option<u8> {
+        let states = self.states.borrow();
+        states[state_index]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn resolve_upvalue(&self, state_index: usize, name: &str) -> Result<Option<u8>, RuntimeError> {
+        if state_index == 0 {
+            return Ok(None);
+        }
+
+        if let Some(local) = self.resolve_local(state_index - 1, name) {
+            self.states.borrow_mut()[state_index - 1].locals[local as usize].is_captured = true;
+            return self.add_upvalue(state_index, local, true).map(Some);
+        }
+
+        match self.resolve_upvalue(state_index - 1, name)? {
+            Some(upvalue) => self.add_upvalue(state_index, upvalue, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&self, state_index: usize, index: u8, is_local: bool) -> Result<u8, RuntimeError> {
+        let mut states = self.states.borrow_mut();
+        let upvalues = &mut states[state_index].upvalues;
+        let upvalue = UpvalueRef { index, is_local };
+
+        if let Some(existing) = upvalues.iter().position(|other| *other == upvalue) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(self.error("Too many closure variables in function."));
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    fn resolve_variable(&self, name: &Token) -> Result<VariableSlot, RuntimeError> {
+        let current = self.states.borrow().len() - 1;
+        if let Some(slot) = self.resolve_local(current, &name.lexeme) {
+            return Ok(VariableSlot::Local(slot));
+        }
+        if let Some(index) = self.resolve_upvalue(current, &name.lexeme)? {
+            return Ok(VariableSlot::Upvalue(index));
+        }
+        Ok(VariableSlot::Global(self.identifier_constant(&name.lexeme)?))
+    }
+
+    //Loads the variable, or stores into it if a value is given
+    fn named_variable(&self, name: &Token, value: Option<Rc<Expr>>) -> Result<(), RuntimeError> {
+        let slot = self.resolve_variable(name)?;
+        let is_assignment = match value {
+            Some(value) => {
+                self.compile_expr(value)?;
+                true
+            }
+            None => false,
+        };
+        self.set_line(name);
+
+        match slot {
+            VariableSlot::Local(index) => {
+                self.emit_op(if is_assignment { OpCode::SetLocal } else { OpCode::GetLocal });
+                self.emit_byte(index);
+            }
+            VariableSlot::Upvalue(index) => {
+                self.emit_op(if is_assignment { OpCode::SetUpvalue } else { OpCode::GetUpvalue });
+                self.emit_byte(index);
+            }
+            VariableSlot::Global(constant) => {
+                let op = if is_assignment { OpCode::SetGlobal } else { OpCode::GetGlobal };
+                self.emit_op_u16(op, constant);
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&self, name: &Token, params: &Vec<Token>, body: &Vec<Rc<Stmt>>, kind: FunctionKind) -> Result<(), RuntimeError> {
+        self.begin_function(name.lexeme.clone(), kind);
+        self.begin_scope();
+
+        for param in params {
+            self.declare_variable(param)?;
+            self.mark_initialized();
+        }
+        self.states.borrow_mut().last_mut().unwrap().proto.arity = params.len() as u32;
+
+        for statement in body {
+            self.compile_stmt(statement.clone())?;
+        }
+
+        //No end_scope needed: the VM discards the whole frame on return
+        let (proto, upvalues) = self.end_function();
+        self.set_line(name);
+        let constant = self.make_constant(Value::Function(Rc::new(proto)))?;
+        self.emit_op_u16(OpCode::Closure, constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+        Ok(())
+    }
+
+    fn synthetic_token(&self, lexeme: &str) -> Token {
+        let kind = if lexeme == "this" { THIS } else { SUPER };
+        Token::synthetic(kind, lexeme, self.line.get())
+    }
+}
+
+impl expr::Visitor<Result<(), RuntimeError>> for Compiler {
+    fn visit_binary_expr(&self, left: Rc<Expr>, operator: &Token, right: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(left)?;
+        self.compile_expr(right)?;
+        self.set_line(operator);
+
+        match operator.kind {
+            PLUS => self.emit_op(OpCode::Add),
+            MINUS => self.emit_op(OpCode::Subtract),
+            STAR => self.emit_op(OpCode::Multiply),
+            SLASH => self.emit_op(OpCode::Divide),
+            PERCENTAGE => self.emit_op(OpCode::Modulo),
+            GREATER => self.emit_op(OpCode::Greater),
+            GREATER_EQUAL => self.emit_op(OpCode::GreaterEqual),
+            LESS => self.emit_op(OpCode::Less),
+            LESS_EQUAL => self.emit_op(OpCode::LessEqual),
+            EQUAL_EQUAL => self.emit_op(OpCode::Equal),
+            BANG_EQUAL => {
+                self.emit_op(OpCode::Equal);
+                self.emit_op(OpCode::Not);
+            }
+            _ => return Err(self.error("Missed Parser Error")), //Unreachable if parser operated properly
+        }
+        Ok(())
+    }
+
+    fn visit_grouping_expr(&self, expression: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)
+    }
+
+    fn visit_literal_expr(&self, value: &LoxValue) -> Result<(), RuntimeError> {
+        match value {
+            LoxValue::Nil => self.emit_op(OpCode::Nil),
+            LoxValue::Boolean(true) => self.emit_op(OpCode::True),
+            LoxValue::Boolean(false) => self.emit_op(OpCode::False),
+            LoxValue::Number(number) => {
+                let constant = self.make_constant(Value::Number(*number))?;
+                self.emit_op_u16(OpCode::Constant, constant);
+            }
+            LoxValue::LoxString(string) => {
+                let constant = self.make_constant(Value::LoxString(Rc::from(string.as_str())))?;
+                self.emit_op_u16(OpCode::Constant, constant);
+            }
+            _ => return Err(self.error("Missed Parser Error")), //The parser only produces primitive literals
+        }
+        Ok(())
+    }
+
+    fn visit_unary_expr(&self, operator: &Token, right: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(right)?;
+        self.set_line(operator);
+
+        match operator.kind {
+            MINUS => self.emit_op(OpCode::Negate),
+            BANG => self.emit_op(OpCode::Not),
+            _ => return Err(self.error("Missed Parser Error")), //Unreachable if parser operated properly
+        }
+        Ok(())
+    }
+
+    fn visit_variable_expr(&self, name: &Token, _expr_pointer_id: usize) -> Result<(), RuntimeError> {
+        self.named_variable(name, None)
+    }
+
+    fn visit_assign_expr(&self, name: &Token, value: Rc<Expr>, _expr_pointer_id: usize) -> Result<(), RuntimeError> {
+        self.named_variable(name, Some(value))
+    }
+
+    fn visit_logical_expr(&self, left: Rc<Expr>, operator: &Token, right: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(left)?;
+        self.set_line(operator);
+
+        if operator.kind == AND {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_op(OpCode::Pop);
+            self.compile_expr(right)?;
+            self.patch_jump(end_jump)
+        } else {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(else_jump)?;
+            self.emit_op(OpCode::Pop);
+            self.compile_expr(right)?;
+            self.patch_jump(end_jump)
+        }
+    }
+
+    fn visit_call_expr(&self, callee: Rc<Expr>, paren: &Token, arguments: &Vec<Rc<Expr>>) -> Result<(), RuntimeError> {
+        //Method calls are compiled to a single Invoke to avoid allocating a bound method
+        let invoke = match callee.as_ref() {
+            Expr::Get { object, name } => {
+                self.compile_expr(object.clone())?;
+                Some((OpCode::Invoke, name))
+            }
+            Expr::Super { keyword: _, method } => {
+                self.named_variable(&self.synthetic_token("this"), None)?;
+                Some((OpCode::SuperInvoke, method))
+            }
+            _ => {
+                self.compile_expr(callee.clone())?;
+                None
+            }
+        };
+
+        for argument in arguments {
+            self.compile_expr(argument.clone())?;
+        }
+        self.set_line(paren);
+
+        match invoke {
+            Some((op, name)) => {
+                if op == OpCode::SuperInvoke {
+                    self.named_variable(&self.synthetic_token("super"), None)?;
+                }
+                let constant = self.identifier_constant(&name.lexeme)?;
+                self.emit_op_u16(op, constant);
+            }
+            None => self.emit_op(OpCode::Call),
+        }
+        self.emit_byte(arguments.len() as u8);
+        Ok(())
+    }
+
+    fn visit_get_expr(&self, object: Rc<Expr>, name: &Token) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        self.set_line(name);
+        let constant = self.identifier_constant(&name.lexeme)?;
+        self.emit_op_u16(OpCode::GetProperty, constant);
+        Ok(())
+    }
+
+    fn visit_set_expr(&self, object: Rc<Expr>, name: &Token, value: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(object)?;
+        self.compile_expr(value)?;
+        self.set_line(name);
+        let constant = self.identifier_constant(&name.lexeme)?;
+        self.emit_op_u16(OpCode::SetProperty, constant);
+        Ok(())
+    }
+
+    fn visit_this_expr(&self, keyword: &Token, _expr_pointer_id: usize) -> Result<(), RuntimeError> {
+        self.named_variable(keyword, None)
+    }
+
+    fn visit_super_expr(&self, keyword: &Token, method: &Token, _expr_pointer_id: usize) -> Result<(), RuntimeError> {
+        self.set_line(keyword);
+        self.named_variable(&self.synthetic_token("this"), None)?;
+        self.named_variable(keyword, None)?;
+        let constant = self.identifier_constant(&method.lexeme)?;
+        self.emit_op_u16(OpCode::GetSuper, constant);
+        Ok(())
+    }
+
+    fn visit_list_expr(&self, _bracket: &Token, _elements: &Vec<Rc<Expr>>) -> Result<(), RuntimeError> {
+        Err(self.error("Lists are not supported by the bytecode VM."))
+    }
+
+    fn visit_map_expr(&self, brace: &Token, _entries: &Vec<(Rc<Expr>, Rc<Expr>)>) -> Result<(), RuntimeError> {
+        self.set_line(brace);
+        Err(self.error("Maps are not supported by the bytecode VM."))
+    }
+
+    fn visit_index_expr(&self, _object: Rc<Expr>, bracket: &Token, _index: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.set_line(bracket);
+        Err(self.error("Indexing is not supported by the bytecode VM."))
+    }
+
+    fn visit_index_set_expr(&self, _object: Rc<Expr>, bracket: &Token, _index: Rc<Expr>, _value: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.set_line(bracket);
+        Err(self.error("Indexing is not supported by the bytecode VM."))
+    }
+
+    fn visit_lambda_expr(&self, keyword: &Token, params: &Vec<Token>, body: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+        self.set_line(keyword);
+        let name = Token::synthetic(IDENTIFIER, "anonymous", keyword.line);
+        self.function(&name, params, body, FunctionKind::Function)
+    }
+}
+
+impl stmt::Visitor<Result<(), RuntimeError>> for Compiler {
+    fn visit_expression_stmt(&self, expression: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)?;
+        self.emit_op(OpCode::Pop);
+        Ok(())
+    }
+
+    fn visit_print_stmt(&self, expression: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.compile_expr(expression)?;
+        self.emit_op(OpCode::Print);
+        Ok(())
+    }
+
+    fn visit_var_stmt(&self, name: Token, initializer: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.declare_variable(&name)?;
+        let global = self.variable_constant(&name)?;
+        self.compile_expr(initializer)?;
+        self.define_variable(global);
+        Ok(())
+    }
+
+    fn visit_block_stmt(&self, _brace: Token, statements: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+        self.begin_scope();
+        for statement in statements {
+            self.compile_stmt(statement.clone())?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_if_stmt(&self, condition: Rc<Expr>, then_branch: Rc<Stmt>, else_branch: &Option<Rc<Stmt>>) -> Result<(), RuntimeError> {
+        self.compile_expr(condition)?;
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.compile_stmt(then_branch)?;
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump)?;
+        self.emit_op(OpCode::Pop);
+
+        if let Some(else_branch) = else_branch {
+            self.compile_stmt(else_branch.clone())?;
+        }
+        self.patch_jump(else_jump)
+    }
+
+    fn visit_while_stmt(&self, condition: Rc<Expr>, body: Rc<Stmt>, increment: &Option<Rc<Expr>>) -> Result<(), RuntimeError> {
+        let loop_start = self.with_chunk(|chunk| chunk.code.len());
+        self.compile_expr(condition)?;
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+
+        let scope_depth = self.states.borrow().last().unwrap().scope_depth;
+        self.states.borrow_mut().last_mut().unwrap().loops.push(LoopState {
+            scope_depth,
+            break_jumps: Vec::new(),
+            continue_jumps: Vec::new(),
+        });
+        let body_result = self.compile_stmt(body);
+        let loop_state = self.states.borrow_mut().last_mut().unwrap().loops.pop().unwrap();
+        body_result?;
+
+        //continue jumps to the increment, which runs before the condition is checked again
+        for jump in loop_state.continue_jumps {
+            self.patch_jump(jump)?;
+        }
+        if let Some(increment) = increment {
+            self.compile_expr(increment.clone())?;
+            self.emit_op(OpCode::Pop);
+        }
+        self.emit_loop(loop_start)?;
+
+        self.patch_jump(exit_jump)?;
+        self.emit_op(OpCode::Pop);
+        //break jumps past the Pop of the condition, which was already popped at the start of the body
+        for jump in loop_state.break_jumps {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+        self.declare_variable(&name)?;
+        let global = self.variable_constant(&name)?;
+        //Functions may refer to themselves, so they are initialized before their body is compiled
+        self.mark_initialized();
+        self.function(&name, &params, &body, FunctionKind::Function)?;
+        self.define_variable(global);
+        Ok(())
+    }
+
+    fn visit_return_stmt(&self, keyword: Token, value: Option<Rc<Expr>>) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        if self.current_kind() == FunctionKind::Script {
+            return Err(self.error("Can't return from top-level code."));
+        }
+
+        match value {
+            Some(value) => self.compile_expr(value)?,
+            None => self.emit_op(OpCode::Nil),
+        }
+        //Initializers always return "this"
+        if self.current_kind() == FunctionKind::Initializer {
+            self.emit_op(OpCode::Pop);
+            self.emit_op(OpCode::GetLocal);
+            self.emit_byte(0);
+        }
+        self.emit_op(OpCode::Return);
+        Ok(())
+    }
+
+    fn visit_class_stmt(&self, name: Token, superclass: &Option<Rc<Expr>>, methods: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+        self.declare_variable(&name)?;
+        let name_constant = self.identifier_constant(&name.lexeme)?;
+        self.emit_op_u16(OpCode::Class, name_constant);
+        self.define_variable(name_constant);
+
+        if let Some(superclass) = superclass {
+            self.compile_expr(superclass.clone())?;
+
+            //"super" is a local in a scope wrapping the methods so they can capture it
+            self.begin_scope();
+            self.add_local("super")?;
+            self.mark_initialized();
+
+            self.named_variable(&name, None)?;
+            self.emit_op(OpCode::Inherit);
+        }
+
+        //Keep the class on the stack while its methods are attached
+        self.named_variable(&name, None)?;
+        for method in methods {
+            if let Stmt::Function { name: method_name, params, body } = method.as_ref() {
+                let constant = self.identifier_constant(&method_name.lexeme)?;
+                let kind = if method_name.lexeme == "init" {
+                    FunctionKind::Initializer
+                } else {
+                    FunctionKind::Method
+                };
+                self.function(method_name, params, body, kind)?;
+                self.emit_op_u16(OpCode::Method, constant);
+            }
+        }
+        self.emit_op(OpCode::Pop);
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    fn visit_break_stmt(&self, keyword: Token) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        self.discard_loop_locals();
+        let jump = self.emit_jump(OpCode::Jump);
+        self.states.borrow_mut().last_mut().unwrap().loops.last_mut().unwrap().break_jumps.push(jump);
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&self, keyword: Token) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        self.discard_loop_locals();
+        let jump = self.emit_jump(OpCode::Jump);
+        self.states.borrow_mut().last_mut().unwrap().loops.last_mut().unwrap().continue_jumps.push(jump);
+        Ok(())
+    }
+
+    fn visit_import_stmt(&self, keyword: Token, _path: Token, _name: Token) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        Err(self.error("Modules are not supported by the bytecode VM."))
+    }
+
+    fn visit_throw_stmt(&self, keyword: Token, _value: Rc<Expr>) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        Err(self.error("Exceptions are not supported by the bytecode VM."))
+    }
+
+    fn visit_try_stmt(&self, keyword: Token, _body: &Vec<Rc<Stmt>>, _catch: &Option<(Token, Vec<Rc<Stmt>>)>, _finally: &Option<Vec<Rc<Stmt>>>) -> Result<(), RuntimeError> {
+        self.set_line(&keyword);
+        Err(self.error("Exceptions are not supported by the bytecode VM."))
+    }
+}
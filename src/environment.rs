@@ -1,12 +1,25 @@
 use crate::{interpreter::RuntimeError, lox_type::LoxValue, token::Token};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
+//Counts the environments of one interpreter that are alive and the values held by them and by its lists, maps and instances. Used by the sandbox
+#[derive(Debug, Default)]
+pub struct EnvironmentStats {
+    pub environments: Cell<usize>,
+    pub values: Cell<usize>,
+}
+
+fn undefined_variable(name: &Token) -> RuntimeError {
+    RuntimeError::new_token(name, ["Undefined variable '", name.lexeme.as_str(), "'."].concat())
+}
+
 #[derive(Debug)]
 pub struct Environment {
     values: HashMap<String, LoxValue>,
     pub enclosing: Option<Rc<RefCell<Environment>>>,
+    //Shared with the enclosing environment, so every environment created by an interpreter updates its stats
+    stats: Option<Rc<EnvironmentStats>>,
 }
 
 impl Environment {
@@ -14,18 +27,38 @@ impl Environment {
         Environment {
             values: HashMap::new(),
             enclosing: None,
+            stats: None,
+        }
+    }
+
+    //An outermost environment that reports to "stats"
+    pub fn new_tracked(stats: Rc<EnvironmentStats>) -> Self {
+        stats.environments.set(stats.environments.get() + 1);
+        Environment {
+            values: HashMap::new(),
+            enclosing: None,
+            stats: Some(stats),
         }
     }
 
     pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        let stats = enclosing.borrow().stats.clone();
+        if let Some(stats) = &stats {
+            stats.environments.set(stats.environments.get() + 1);
+        }
         Environment {
             values: HashMap::new(),
             enclosing: Some(enclosing),
+            stats,
         }
     }
 
     pub fn define(&mut self, name: String, value: LoxValue) {
-        self.values.insert(name, value);
+        if self.values.insert(name, value).is_none() {
+            if let Some(stats) = &self.stats {
+                stats.values.set(stats.values.get() + 1);
+            }
+        }
     }
 
     //All variables are passed by value (clone preforms a copy), functions are passed by reference
@@ -35,19 +68,21 @@ impl Environment {
             //Not found, check enclosing env if it exists
             None => match self.enclosing {
                 Some(ref env) => env.borrow().get(name),
-                None => Err(RuntimeError::new(
-                    [
-                        "Undefined variable '".to_string(),
-                        name.lexeme.clone(),
-                        "'.".to_string(),
-                    ]
-                    .concat(),
-                    name.line,
-                )),
+                None => Err(undefined_variable(name)),
             },
         }
     }
 
+    //Looks a name up in this environment only, ignoring the enclosing ones
+    pub fn get_own(&self, name: &str) -> Option<LoxValue> {
+        self.values.get(name).cloned()
+    }
+
+    //Names defined in this environment only
+    pub fn names(&self) -> Vec<String> {
+        self.values.keys().cloned().collect()
+    }
+
     //get the ancestor enclosing environment at "distance (>0)"
     fn ancestor(&self, distance: usize) -> Rc<RefCell<Environment>> {
         if distance == 1 {
@@ -66,28 +101,12 @@ impl Environment {
         if distance == 0 {
             match self.values.get(&name.lexeme) {
                 Some(val) => Ok(val.clone()),
-                None => Err(RuntimeError::new(
-                    [
-                        "Undefined variable '".to_string(),
-                        name.lexeme.clone(),
-                        "'.".to_string(),
-                    ]
-                    .concat(),
-                    name.line,
-                )),
+                None => Err(undefined_variable(name)),
             }
         } else {
             match self.ancestor(distance).borrow().values.get(&name.lexeme) {
                 Some(val) => Ok(val.clone()),
-                None => Err(RuntimeError::new(
-                    [
-                        "Undefined variable '".to_string(),
-                        name.lexeme.clone(),
-                        "'.".to_string(),
-                    ]
-                    .concat(),
-                    name.line,
-                )),
+                None => Err(undefined_variable(name)),
             }
         }
     }
@@ -100,15 +119,7 @@ impl Environment {
         } else {
             match self.enclosing {
                 Some(ref env) => env.borrow_mut().assign(name, &value),
-                None => Err(RuntimeError::new(
-                    [
-                        "Undefined variable '".to_string(),
-                        name.lexeme.clone(),
-                        "'.".to_string(),
-                    ]
-                    .concat(),
-                    name.line,
-                )),
+                None => Err(undefined_variable(name)),
             }
         }
     }
@@ -120,15 +131,24 @@ impl Environment {
         value: &LoxValue,
     ) -> Result<(), RuntimeError> {
         let value = value.clone();
+        //define also counts the variable for the sandbox if it is new
         if distance == 0 {
-            self.values.insert(name.lexeme.clone(), value);
+            self.define(name.lexeme.clone(), value);
             Ok(())
         } else {
             self.ancestor(distance)
                 .borrow_mut()
-                .values
-                .insert(name.lexeme.clone(), value);
+                .define(name.lexeme.clone(), value);
             Ok(())
         }
     }
 }
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        if let Some(stats) = &self.stats {
+            stats.environments.set(stats.environments.get() - 1);
+            stats.values.set(stats.values.get() - self.values.len());
+        }
+    }
+}
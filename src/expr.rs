@@ -1,4 +1,5 @@
 use crate::lox_type::LoxValue;
+use crate::stmt::Stmt;
 use crate::token::Token;
 use std::rc::Rc;
 
@@ -14,7 +15,17 @@ pub enum Expr {
     Variable{name: Token},
     Assign{name: Token, value: Rc<Expr>},
     Logical{left: Rc<Expr>, operator: Token, right: Rc<Expr>},
-    Call{callee: Rc<Expr>, paren: Token, arguments: Vec<Rc<Expr>>}
+    Call{callee: Rc<Expr>, paren: Token, arguments: Vec<Rc<Expr>>},
+    Get{object: Rc<Expr>, name: Token},
+    Set{object: Rc<Expr>, name: Token, value: Rc<Expr>},
+    This{keyword: Token},
+    Super{keyword: Token, method: Token},
+    List{bracket: Token, elements: Vec<Rc<Expr>>},
+    Map{brace: Token, entries: Vec<(Rc<Expr>, Rc<Expr>)>},
+    Index{object: Rc<Expr>, bracket: Token, index: Rc<Expr>},
+    IndexSet{object: Rc<Expr>, bracket: Token, index: Rc<Expr>, value: Rc<Expr>},
+    //An anonymous function: fun (params) { body }
+    Lambda{keyword: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>}
 }
 
 
@@ -29,6 +40,15 @@ pub trait Visitor<T> {
     fn visit_assign_expr(&self, name: &Token, value: Rc<Expr>, expr_pointer_id: usize) -> T;
     fn visit_logical_expr(&self, left: Rc<Expr>, operator: &Token, right: Rc<Expr>) -> T;
     fn visit_call_expr(&self, callee: Rc<Expr>, paren: &Token, arguments: &Vec<Rc<Expr>>) -> T;
+    fn visit_get_expr(&self, object: Rc<Expr>, name: &Token) -> T;
+    fn visit_set_expr(&self, object: Rc<Expr>, name: &Token, value: Rc<Expr>) -> T;
+    fn visit_this_expr(&self, keyword: &Token, expr_pointer_id: usize) -> T;
+    fn visit_super_expr(&self, keyword: &Token, method: &Token, expr_pointer_id: usize) -> T;
+    fn visit_list_expr(&self, bracket: &Token, elements: &Vec<Rc<Expr>>) -> T;
+    fn visit_map_expr(&self, brace: &Token, entries: &Vec<(Rc<Expr>, Rc<Expr>)>) -> T;
+    fn visit_index_expr(&self, object: Rc<Expr>, bracket: &Token, index: Rc<Expr>) -> T;
+    fn visit_index_set_expr(&self, object: Rc<Expr>, bracket: &Token, index: Rc<Expr>, value: Rc<Expr>) -> T;
+    fn visit_lambda_expr(&self, keyword: &Token, params: &Vec<Token>, body: &Vec<Rc<Stmt>>) -> T;
 }
 
 //This trait should be implemented if the visitor seeks to use the a reference to the Expr itself
@@ -42,6 +62,15 @@ pub trait VisitorExpr<T> {
     fn visit_assign_expr(&self, expr: Rc<Expr>) -> T;
     fn visit_logical_expr(&self, expr: Rc<Expr>) -> T;
     fn visit_call_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_get_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_set_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_this_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_super_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_list_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_map_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_index_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_index_set_expr(&self, expr: Rc<Expr>) -> T;
+    fn visit_lambda_expr(&self, expr: Rc<Expr>) -> T;
 }
 
 
@@ -57,6 +86,15 @@ impl Expr{
             Self::Assign {name, value} => visitor.visit_assign_expr(name, value.clone(), (self as *const Expr) as usize),
             Self::Logical { left, operator, right} => visitor.visit_logical_expr(left.clone(), operator, right.clone()),
             Self::Call { callee, paren, arguments} => visitor.visit_call_expr(callee.clone(), paren, arguments),
+            Self::Get { object, name } => visitor.visit_get_expr(object.clone(), name),
+            Self::Set { object, name, value } => visitor.visit_set_expr(object.clone(), name, value.clone()),
+            Self::This { keyword } => visitor.visit_this_expr(keyword, (self as *const Expr) as usize),
+            Self::Super { keyword, method } => visitor.visit_super_expr(keyword, method, (self as *const Expr) as usize),
+            Self::List { bracket, elements } => visitor.visit_list_expr(bracket, elements),
+            Self::Map { brace, entries } => visitor.visit_map_expr(brace, entries),
+            Self::Index { object, bracket, index } => visitor.visit_index_expr(object.clone(), bracket, index.clone()),
+            Self::IndexSet { object, bracket, index, value } => visitor.visit_index_set_expr(object.clone(), bracket, index.clone(), value.clone()),
+            Self::Lambda { keyword, params, body } => visitor.visit_lambda_expr(keyword, params, body),
         }
     }
 
@@ -70,6 +108,15 @@ impl Expr{
             Self::Assign {name:_, value:_} => visitor.visit_assign_expr(expr),
             Self::Logical { left:_, operator:_, right:_} => visitor.visit_logical_expr(expr),
             Self::Call { callee:_, paren:_, arguments:_} => visitor.visit_call_expr(expr),
+            Self::Get { object:_, name:_ } => visitor.visit_get_expr(expr),
+            Self::Set { object:_, name:_, value:_ } => visitor.visit_set_expr(expr),
+            Self::This { keyword:_ } => visitor.visit_this_expr(expr),
+            Self::Super { keyword:_, method:_ } => visitor.visit_super_expr(expr),
+            Self::List { bracket:_, elements:_ } => visitor.visit_list_expr(expr),
+            Self::Map { brace:_, entries:_ } => visitor.visit_map_expr(expr),
+            Self::Index { object:_, bracket:_, index:_ } => visitor.visit_index_expr(expr),
+            Self::IndexSet { object:_, bracket:_, index:_, value:_ } => visitor.visit_index_set_expr(expr),
+            Self::Lambda { keyword:_, params:_, body:_ } => visitor.visit_lambda_expr(expr),
         }
     }
 }
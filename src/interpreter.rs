@@ -1,17 +1,38 @@
-use crate::environment::Environment;
+use crate::environment::{Environment, EnvironmentStats};
 use crate::expr::{self, Expr};
-use crate::lox_callable::LoxCallable;
+use crate::io_natives::define_io_natives;
+use crate::list_natives::define_list_natives;
+use crate::lox_callable::{Arity, LoxCallable};
+use crate::lox_class::LoxClass;
 use crate::lox_function::LoxFunction;
+use crate::lox_instance::LoxInstance;
+use crate::lox_error::{Diagnostic, RESOLVE_ERROR};
+use crate::lox_io::{LoxIo, StdIo};
+use crate::lox_list::LoxList;
+use crate::lox_map::LoxMap;
+use crate::lox_module::LoxModule;
 use crate::lox_type::LoxValue::{self, *};
-use crate::native_function::NativeFunction;
+use crate::map_natives::define_map_natives;
+use crate::math_natives::define_math_natives;
+use crate::native_function::define_native;
+use crate::parser::Parser;
+use crate::random::Rng;
+use crate::random_natives::define_random_natives;
+use crate::resolver::Resolver;
+use crate::sandbox::SandboxConfig;
+use crate::scanner::Scanner;
 use crate::stmt::{self, Stmt};
+use crate::string_natives::define_string_natives;
 use crate::token::{Token, TokenType::*};
-use std::cell::RefCell;
+use crate::type_natives::define_type_natives;
+use std::cell::{Cell, RefCell, RefMut};
 use std::collections::HashMap;
 use std::fmt::Write;
+use std::fs;
 use std::io;
-use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 
 pub struct Interpreter {
@@ -19,63 +40,340 @@ pub struct Interpreter {
     //It is required because Rust's borrow checker is strict in that you can only have one mutable reference to a value
     //Pattern for handling environment references from: https://github.com/UncleScientist/lox-ast
     pub globals: Rc<RefCell<Environment>>,
+    //Native functions live here. The globals of the main program and of every module enclose it
+    builtins: Rc<RefCell<Environment>>,
     environment: RefCell<Rc<RefCell<Environment>>>,
-    locals: RefCell<HashMap<usize, usize>>,
+    //Scope depth of each resolved local, keyed by the address of its expression. The Weak keeps that memory from being
+    //reused by another expression and shows when the program has been dropped, see release_dropped_locals
+    locals: RefCell<HashMap<usize, (Weak<Expr>, usize)>>,
     output: RefCell<String>,
+    //Files currently being executed, innermost last. Imports are relative to the last one
+    files: RefCell<Vec<PathBuf>>,
+    //Every module imported so far, by canonical path. A file is only ever run once
+    modules: RefCell<HashMap<PathBuf, Rc<LoxModule>>>,
+    //Class of the values bound by catch clauses
+    error_class: Rc<LoxClass>,
+    //Functions currently being called, innermost last. Used for stack traces
+    call_frames: RefCell<Vec<CallFrame>>,
+    //Calls nested deeper than this raise "Stack overflow." instead of overflowing the native stack
+    max_call_depth: Cell<usize>,
+    native_stack_size: Cell<usize>,
+    //Stack address when the current run started
+    stack_base: Cell<usize>,
+    //Limits on a single call to interpret. Statements, loop iterations and calls each take a step
+    step_limit: Cell<Option<u64>>,
+    time_limit: Cell<Option<Duration>>,
+    steps: Cell<u64>,
+    deadline: Cell<Option<Instant>>,
+    sandbox: Cell<SandboxConfig>,
+    //Shared by every environment of this interpreter
+    stats: Rc<EnvironmentStats>,
+    //Bytes of the strings created by the current run
+    string_bytes: Cell<usize>,
+    //Console and filesystem used by the I/O natives
+    io: RefCell<Rc<dyn LoxIo>>,
+    //Pseudo-random numbers for random(), random_int() and shuffle()
+    rng: RefCell<Rng>,
+}
+
+//Lox calls nested deeper than this report a stack overflow
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 400;
+
+//Each Lox call uses several nested Rust calls, from about 3 KiB of native stack in release builds to 16 KiB or much more
+//in debug builds, depending on how deeply the call is nested in expressions and blocks. So calls also stop with a stack
+//overflow once they have used three quarters of the native stack. The default is the size Rust gives spawned threads;
+//the main thread usually has 8 MiB
+pub const DEFAULT_NATIVE_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+//Address of the caller's stack frame, used to measure how much native stack is in use
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+struct CallFrame {
+    name: String,
+    //Line of the call in the caller
+    line: u32,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let globals = Rc::new(RefCell::new(Environment::new()));
+        let stats = Rc::new(EnvironmentStats::default());
+        let builtins = Rc::new(RefCell::new(Environment::new_tracked(stats.clone())));
+        let globals = Rc::new(RefCell::new(Environment::new_enclosed(builtins.clone())));
         //Clone used on an Rc creates just another reference to the same data
         let environment = RefCell::new(globals.clone());
 
         //Native functions:
-        fn clock(_arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> LoxValue {
+        fn clock(_arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
             //Code for clock from https://stackoverflow.com/questions/26593387/how-can-i-get-the-current-time-in-milliseconds
             let start = SystemTime::now();
             let since_the_epoch = start
                 .duration_since(UNIX_EPOCH)
                 .expect("Time went backwards");
-            LoxValue::Number(since_the_epoch.as_millis() as f64)
+            Ok(LoxValue::Number(since_the_epoch.as_millis() as f64))
         }
 
 
         //define native functions
-        globals.borrow_mut().define(
-            "clock".to_string(),
-            LoxValue::Native(Rc::new(NativeFunction {
-                arity: 0,
-                function: clock,
-            })),
-        );
+        define_native(&mut builtins.borrow_mut(), "clock", 0, clock);
+        define_list_natives(&mut builtins.borrow_mut());
+        define_map_natives(&mut builtins.borrow_mut());
+        define_math_natives(&mut builtins.borrow_mut());
+        define_string_natives(&mut builtins.borrow_mut());
+        define_io_natives(&mut builtins.borrow_mut());
+        define_type_natives(&mut builtins.borrow_mut());
+        define_random_natives(&mut builtins.borrow_mut());
 
 
         Interpreter {
             globals,
+            builtins,
             environment,
             locals: RefCell::new(HashMap::new()),
             //output temporarily stores the result of print statements 
             output: RefCell::new("".to_string()),
+            files: RefCell::new(Vec::new()),
+            modules: RefCell::new(HashMap::new()),
+            error_class: Rc::new(LoxClass {
+                name: "Error".to_string(),
+                superclass: None,
+                methods: HashMap::new(),
+            }),
+            call_frames: RefCell::new(Vec::new()),
+            max_call_depth: Cell::new(DEFAULT_MAX_CALL_DEPTH),
+            native_stack_size: Cell::new(DEFAULT_NATIVE_STACK_SIZE),
+            stack_base: Cell::new(stack_address()),
+            step_limit: Cell::new(None),
+            time_limit: Cell::new(None),
+            steps: Cell::new(0),
+            deadline: Cell::new(None),
+            sandbox: Cell::new(SandboxConfig::default()),
+            stats,
+            string_bytes: Cell::new(0),
+            io: RefCell::new(Rc::new(StdIo)),
+            rng: RefCell::new(Rng::from_time()),
         }
     }
-    pub fn interpret(&self, statements : Vec<Rc<Stmt>>, output_stream: &mut dyn io::Write) -> Result<(), RuntimeError>{    
-        for statement in statements{
-            match self.execute(statement){
-                Ok(()) => (),
-                Err(error) => return Err(error),
+
+    //Exposes a host function to scripts. It is visible in every module and replaces any native of the same name.
+    //The closure may capture state; it receives the arguments after the interpreter has checked them against "arity"
+    pub fn register_native(
+        &self,
+        name: &str,
+        arity: impl Into<Arity>,
+        function: impl Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError> + 'static,
+    ) {
+        define_native(&mut self.builtins.borrow_mut(), name, arity, function);
+    }
+
+    pub fn set_sandbox(&self, config: SandboxConfig) {
+        self.sandbox.set(config);
+    }
+
+    pub fn sandbox(&self) -> SandboxConfig {
+        self.sandbox.get()
+    }
+
+    //Checks the sandbox limits on live environments and values. Called before anything that creates an environment or variable
+    fn check_sandbox(&self, token: &Token) -> Result<(), RuntimeError> {
+        let sandbox = self.sandbox.get();
+        let exceeded = |limit: Option<usize>, used: usize| limit.filter(|limit| used >= *limit);
+        if let Some(limit) = exceeded(sandbox.max_environments, self.stats.environments.get()) {
+            return Err(RuntimeError::new_token(
+                token,
+                format!("Sandbox limit exceeded: more than {} live environments.", limit),
+            ));
+        }
+        if let Some(limit) = exceeded(sandbox.max_values, self.stats.values.get()) {
+            return Err(RuntimeError::new_token(
+                token,
+                format!("Sandbox limit exceeded: more than {} live values.", limit),
+            ));
+        }
+        Ok(())
+    }
+
+    //Accounts for a new string of "bytes" bytes. Natives that build strings call this too
+    pub(crate) fn allocate_string(&self, bytes: usize) -> Result<(), String> {
+        let total = self.string_bytes.get() + bytes;
+        self.string_bytes.set(total);
+        match self.sandbox.get().max_string_bytes {
+            Some(limit) if total > limit => Err(format!(
+                "Sandbox limit exceeded: more than {} bytes of strings.",
+                limit
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    //Replaces the console and filesystem seen by the I/O natives, e.g. with a MemoryIo
+    pub fn set_io(&self, io: Rc<dyn LoxIo>) {
+        *self.io.borrow_mut() = io;
+    }
+
+    //The I/O natives call this, so a sandbox that denies I/O stops them before they touch anything
+    pub(crate) fn io(&self) -> Result<Rc<dyn LoxIo>, String> {
+        if self.sandbox.get().deny_io {
+            return Err("Sandbox does not allow console or file access.".to_string());
+        }
+        Ok(self.io.borrow().clone())
+    }
+
+    //Makes the random natives reproducible. Scripts can do the same with seed(n)
+    pub fn set_random_seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = Rng::new(seed);
+    }
+
+    pub(crate) fn rng(&self) -> RefMut<'_, Rng> {
+        self.rng.borrow_mut()
+    }
+
+    //Checks that "count" more live values fit in the sandbox and accounts for the strings in "stored", which are
+    //copied into a list or map. Call this before adding anything to one
+    pub(crate) fn check_store(&self, count: usize, stored: &[LoxValue]) -> Result<(), String> {
+        if let Some(limit) = self.sandbox.get().max_values {
+            if self.stats.values.get() + count > limit {
+                return Err(format!("Sandbox limit exceeded: more than {} live values.", limit));
+            }
+        }
+        let bytes = stored
+            .iter()
+            .map(|value| match value {
+                LoxString(string) => string.len(),
+                _ => 0,
+            })
+            .sum();
+        self.allocate_string(bytes)
+    }
+
+    //Lists, maps and instances made by the interpreter count their contents against the sandbox's value limit
+    pub(crate) fn new_list(&self, elements: Vec<LoxValue>) -> Rc<LoxList> {
+        Rc::new(LoxList::new_tracked(elements, self.stats.clone()))
+    }
+
+    pub(crate) fn new_map(&self) -> Rc<LoxMap> {
+        Rc::new(LoxMap::new_tracked(self.stats.clone()))
+    }
+
+    pub(crate) fn new_instance(&self, class: Rc<LoxClass>) -> Rc<LoxInstance> {
+        Rc::new(LoxInstance::new_tracked(class, self.stats.clone()))
+    }
+
+    //Sets a map entry after checking it against the sandbox. Only a new key adds live values
+    pub(crate) fn map_set(&self, map: &LoxMap, key: LoxValue, value: LoxValue) -> Result<(), String> {
+        if map.has(&key)? {
+            self.check_store(0, std::slice::from_ref(&value))?;
+        } else {
+            self.check_store(2, &[key.clone(), value.clone()])?;
+        }
+        map.set(key, value)
+    }
+
+    //Bytes of strings that can still be created before allocate_string fails
+    pub(crate) fn string_budget(&self) -> usize {
+        match self.sandbox.get().max_string_bytes {
+            Some(limit) => limit.saturating_sub(self.string_bytes.get()),
+            None => usize::MAX,
+        }
+    }
+
+    //Stops each run after this many steps with an execution limit error. None removes the limit
+    pub fn set_step_limit(&self, limit: Option<u64>) {
+        self.step_limit.set(limit);
+    }
+
+    //Stops each run once it has taken this long with an execution limit error. None removes the limit
+    pub fn set_time_limit(&self, limit: Option<Duration>) {
+        self.time_limit.set(limit);
+    }
+
+    //Counts a step and checks the execution limits
+    fn tick(&self) -> Result<(), RuntimeError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(limit) = self.step_limit.get() {
+            if steps > limit {
+                return Err(RuntimeError::execution_limit(format!(
+                    "Execution limit exceeded: more than {} steps.",
+                    limit
+                )));
+            }
+        }
+        //Reading the clock is slow compared to a step, so it is only done every so often
+        if let Some(deadline) = self.deadline.get() {
+            if steps % 64 == 0 && Instant::now() > deadline {
+                let limit = self.time_limit.get().unwrap_or_default();
+                return Err(RuntimeError::execution_limit(format!(
+                    "Execution limit exceeded: ran for more than {} ms.",
+                    limit.as_millis()
+                )));
             }
-            //Write to outputs_stream from output String
+        }
+        Ok(())
+    }
+
+    //Deeper limits also need a larger native stack, see set_native_stack_size
+    pub fn set_max_call_depth(&self, depth: usize) {
+        self.max_call_depth.set(depth);
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth.get()
+    }
+
+    //Tells the interpreter the size of the native stack of the thread it runs on, e.g. one made by with_stack_size.
+    //A size larger than the real stack lets deep recursion abort the process
+    pub fn set_native_stack_size(&self, bytes: usize) {
+        self.native_stack_size.set(bytes);
+    }
+
+    //Marks a script as being executed so that its imports are resolved relative to it
+    pub fn push_file(&self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.files.borrow_mut().push(path);
+    }
+
+    pub fn pop_file(&self) {
+        self.files.borrow_mut().pop();
+    }
+    //Returns the value of the final statement if it is an expression statement, otherwise nil
+    pub fn interpret(&self, statements : Vec<Rc<Stmt>>, output_stream: &mut dyn io::Write) -> Result<LoxValue, RuntimeError>{    
+        self.steps.set(0);
+        self.string_bytes.set(0);
+        self.stack_base.set(stack_address());
+        self.release_dropped_locals();
+        self.deadline.set(self.time_limit.get().map(|limit| Instant::now() + limit));
+
+        let mut value = LoxValue::Nil;
+        let count = statements.len();
+        for (i, statement) in statements.into_iter().enumerate() {
+            let result = match statement.as_ref() {
+                Stmt::Expression { expression } if i + 1 == count => {
+                    self.evaluate(expression.clone()).map(|val| value = val).map_err(Unwind::Error)
+                }
+                _ => self.execute(statement),
+            };
+            //Write to outputs_stream from output String. Output printed before an error is kept
             output_stream
                 .write(self.output.borrow().as_bytes())
                 .expect("Could not write to provided output buffer.");
             output_stream.flush().expect("Could not flush to output buffer.");
             self.output.borrow_mut().clear();
+            //The Resolver rejects return, break and continue outside of functions and loops, so only errors reach here
+            if let Err(Unwind::Error(mut error)) = result {
+                if error.trace.is_empty() && error.kind == ErrorKind::Error {
+                    error.trace = self.stack_trace(error.line);
+                }
+                return Err(error);
+            }
         }
-        Ok(())
+        Ok(value)
     }
 
-    fn execute(&self, stmt: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn execute(&self, stmt: Rc<Stmt>) -> Result<(), Unwind> {
+        self.tick()?;
         stmt.accept(self)
     }
 
@@ -87,10 +385,21 @@ impl Interpreter {
         //It turns out that we already have a unique id: The memory address of the expression
         //Pattern inspired by: https://github.com/UncleScientist/lox-ast/blob/4f56ce6979a3e5eb21b26aaa9b0dbef4860b1474/generate_ast/mod.rs#L106
         let pointer_val = Rc::as_ptr(&expr) as usize;
-        self.locals.borrow_mut().insert(pointer_val, depth);
+        self.locals.borrow_mut().insert(pointer_val, (Rc::downgrade(&expr), depth));
+    }
+
+    //Forgets the locals of programs that have been dropped, so that a long-lived interpreter that evaluates many
+    //programs, like the REPL, doesn't keep them all. Functions keep their bodies, and so their locals, alive
+    fn release_dropped_locals(&self) {
+        self.locals.borrow_mut().retain(|_, (expr, _)| expr.strong_count() > 0);
+    }
+
+    //Number of resolved locals currently held
+    pub fn resolved_locals(&self) -> usize {
+        self.locals.borrow().len()
     }
 
-    pub fn execute_block(&self, statements: &Vec<Rc<Stmt>>, environment : Environment) -> Result<(), RuntimeError>{
+    pub fn execute_block(&self, statements: &Vec<Rc<Stmt>>, environment : Environment) -> Result<(), Unwind>{
         //The environment swapping that occurs here is against the grain of Rust.
         //While we are executing a block we never edit "previous" but the Rust borrow checker can't tell that.
         //We use the outer RefCell in "self.environment" to work around this. 
@@ -98,7 +407,7 @@ impl Interpreter {
         let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
 
         for statement in statements {
-            //This match statement ensure that the environments will be swapped back even if there is a RuntimeError
+            //This match statement ensure that the environments will be swapped back even if the block unwinds
             //This is essential if there is a return statement in 'statements'
             match self.execute(statement.clone()) {
                 Ok(()) => (),
@@ -117,8 +426,9 @@ impl Interpreter {
         expr.accept(self)
     }
 
-    fn call_function(&self, func: &dyn LoxCallable, arguments: Vec<LoxValue>, paren: &Token) -> Result<LoxValue, RuntimeError> {
-        if arguments.len() != func.arity() as usize {
+    //The trace of an error is taken in the innermost call it leaves, while the frames of all callers are still known
+    fn call_function(&self, func: &dyn LoxCallable, name: &str, arguments: Vec<LoxValue>, paren: &Token) -> Result<LoxValue, RuntimeError> {
+        if !func.arity().accepts(arguments.len()) {
             Err(RuntimeError::new_token(
                 paren,
                 [
@@ -130,22 +440,169 @@ impl Interpreter {
                 ]
                 .concat(),
             ))
+        } else if self.call_frames.borrow().len() >= self.effective_max_call_depth()
+            || self.stack_base.get().abs_diff(stack_address()) > self.native_stack_size.get() / 4 * 3
+        {
+            Err(RuntimeError::new_token(paren, "Stack overflow.".to_string()))
         } else {
-            func.call(self, arguments)
+            self.check_sandbox(paren)?;
+            self.call_frames.borrow_mut().push(CallFrame {
+                name: name.to_string(),
+                line: paren.line,
+            });
+            let result = func.call(self, arguments).map_err(|mut error| {
+                //Errors raised by natives usually have no line yet. The caller places them first, see visit_call_expr
+                if error.trace.is_empty() && error.kind == ErrorKind::Error && error.line != 0 {
+                    error.trace = self.stack_trace(error.line);
+                }
+                error
+            });
+            self.call_frames.borrow_mut().pop();
+            result
+        }
+    }
+
+    fn effective_max_call_depth(&self) -> usize {
+        let sandbox_depth = self.sandbox.get().max_call_depth.unwrap_or(usize::MAX);
+        self.max_call_depth.get().min(sandbox_depth)
+    }
+
+    //Formats the call stack innermost first. "line" is the line being executed by the innermost call
+    fn stack_trace(&self, line: u32) -> Vec<String> {
+        let mut trace = Vec::new();
+        let mut line = line;
+        for frame in self.call_frames.borrow().iter().rev() {
+            trace.push(format!("[line {}] in {}()", line, frame.name));
+            line = frame.line;
         }
+        trace.push(format!("[line {}] in script", line));
+        trace
     }
 
     //uses expression pointer ID to find the expression resolve distance added to "self.locals" by the Resolver
     fn look_up_variable(&self, name: &Token, expr_pointer_id: usize) -> Result<LoxValue, RuntimeError> {
         match self.locals.borrow().get(&expr_pointer_id) {
-            Some(dist) => self
+            Some((_, dist)) => self
                 .environment
                 .borrow()
                 .borrow()
-                .get_at(dist.clone(), name),
-            None => self.globals.borrow().get(name),
+                .get_at(*dist, name),
+            None => self.global_environment().borrow().get(name),
         }
     }
+
+    //The global environment of the file the running code was defined in. It is the outermost environment
+    //before the builtins, so functions from a module keep seeing that module's globals
+    //Names of the globals defined by the programs run so far
+    pub fn global_names(&self) -> Vec<String> {
+        self.global_environment().borrow().names()
+    }
+
+    fn global_environment(&self) -> Rc<RefCell<Environment>> {
+        let mut environment = self.environment.borrow().clone();
+        loop {
+            let enclosing = environment.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) if !Rc::ptr_eq(&enclosing, &self.builtins) => environment = enclosing,
+                _ => return environment,
+            }
+        }
+    }
+
+    //Converts a caught error into the value bound by a catch clause: an Error instance with the message, line and
+    //thrown value (nil for errors raised by the interpreter). Rethrown Error instances are caught as they are
+    fn error_value(&self, error: RuntimeError) -> LoxValue {
+        if let Some(Instance(instance)) = error.value.as_deref() {
+            if Rc::ptr_eq(&instance.class, &self.error_class) {
+                return Instance(instance.clone());
+            }
+        }
+
+        let instance = self.new_instance(self.error_class.clone());
+        let field = |name: &str| Token::synthetic(IDENTIFIER, name, error.line);
+        instance.set(&field("message"), LoxString(error.message.clone()));
+        instance.set(&field("line"), Number(error.line as f64));
+        instance.set(&field("value"), error.value.map(|value| *value).unwrap_or(Nil));
+        Instance(instance)
+    }
+
+    //Runs the file named by "path" (a STRING token) in its own global environment, or returns the cached module
+    fn import_module(&self, path: &Token) -> Result<Rc<LoxModule>, RuntimeError> {
+        let relative = path.literal.to_string();
+        let base = match self.files.borrow().last() {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        let full_path = fs::canonicalize(base.join(&relative)).map_err(|error| {
+            RuntimeError::new_token(path, ["Could not open module '", relative.as_str(), "': ", error.to_string().as_str(), "."].concat())
+        })?;
+
+        if let Some(module) = self.modules.borrow().get(&full_path) {
+            return Ok(module.clone());
+        }
+
+        //A module that is still running when it is imported again is part of a cycle
+        if let Some(start) = self.files.borrow().iter().position(|file| *file == full_path) {
+            let chain: Vec<String> = self.files.borrow()[start..]
+                .iter()
+                .chain(std::iter::once(&full_path))
+                .map(|file| file.file_name().unwrap_or_default().to_string_lossy().to_string())
+                .collect();
+            return Err(RuntimeError::new_token(
+                path,
+                ["Import cycle detected: ", chain.join(" -> ").as_str(), "."].concat(),
+            ));
+        }
+
+        let source = fs::read_to_string(&full_path).map_err(|error| {
+            RuntimeError::new_token(path, ["Could not open module '", relative.as_str(), "': ", error.to_string().as_str(), "."].concat())
+        })?;
+
+        //Static errors in the module are reported at the import, with the module's diagnostics as notes
+        let static_error = |diagnostics: Vec<String>| {
+            let mut error = RuntimeError::new_token(path, ["Could not compile module '", relative.as_str(), "'."].concat());
+            error.notes = diagnostics;
+            error
+        };
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner
+            .scan_tokens()
+            .map_err(|errors| static_error(errors.iter().map(|error| error.to_string()).collect()))?;
+        let statements = Parser::new(tokens)
+            .parse()
+            .map_err(|errors| static_error(errors.iter().map(|error| error.to_string()).collect()))?;
+        Resolver::new(self).resolve_program(&statements).map_err(|errors| {
+            static_error(errors.into_iter().map(|error| Diagnostic::from_runtime_error(RESOLVE_ERROR, error).to_string()).collect())
+        })?;
+
+        let environment = Rc::new(RefCell::new(Environment::new_enclosed(self.builtins.clone())));
+        let previous = self.environment.replace(environment.clone());
+        self.files.borrow_mut().push(full_path.clone());
+        let mut result = Ok(());
+        for statement in &statements {
+            //The Resolver rejects return, break and continue at the top level, so only errors reach here
+            if let Err(Unwind::Error(error)) = self.execute(statement.clone()) {
+                result = Err(error);
+                break;
+            }
+        }
+        self.files.borrow_mut().pop();
+        self.environment.replace(previous);
+
+        //The error is moved to the import so that it points into the importing file
+        result.map_err(|error| {
+            let mut moved = RuntimeError::new_token(path, error.message);
+            moved.value = error.value;
+            moved.kind = error.kind;
+            moved.notes.push(format!("in module '{}' at line {}", relative, error.line));
+            moved.notes.extend(error.notes);
+            moved
+        })?;
+
+        let module = Rc::new(LoxModule::new(full_path.clone(), environment, statements));
+        self.modules.borrow_mut().insert(full_path, module.clone());
+        Ok(module)
+    }
 }
 
 fn invalid_operand_number(operator: &Token) -> RuntimeError {
@@ -167,9 +624,11 @@ impl expr::Visitor<Result<LoxValue, RuntimeError>> for Interpreter {
                     )),
                 },
                 LoxString(left_val) => match right_eval {
-                    LoxString(right_val) => Ok(LoxString(
-                        [left_val.as_str(), right_val.as_str()].concat().to_string(),
-                    )),
+                    LoxString(right_val) => {
+                        self.allocate_string(left_val.len() + right_val.len())
+                            .map_err(|message| RuntimeError::new_token(operator, message))?;
+                        Ok(LoxString([left_val.as_str(), right_val.as_str()].concat()))
+                    }
                     _ => Err(RuntimeError::new_token(
                         operator,
                         "Operand types do not match".to_string(),
@@ -254,6 +713,7 @@ impl expr::Visitor<Result<LoxValue, RuntimeError>> for Interpreter {
 
 
     fn visit_call_expr(&self, callee: Rc<Expr>, paren : &Token, arguments : &Vec<Rc<Expr>>) -> Result<LoxValue, RuntimeError>{
+        self.tick()?;
         let callee_val = self.evaluate(callee)?;
 
         let mut argument_vals = vec![];
@@ -262,8 +722,23 @@ impl expr::Visitor<Result<LoxValue, RuntimeError>> for Interpreter {
         }
 
         match callee_val {
-            Function(func) => self.call_function(func.as_ref(), argument_vals, paren),
-            Native(func) => self.call_function(func.as_ref(), argument_vals, paren),
+            Function(func) => self.call_function(func.as_ref(), &func.name(), argument_vals, paren),
+            //Errors raised by natives without a position are reported at the call. Everything else about them is kept,
+            //including their kind, so a host native can stop the script with an execution limit error
+            Native(func) => self
+                .call_function(func.as_ref(), &func.name, argument_vals, paren)
+                .map_err(|mut error| {
+                    if error.line == 0 && error.kind == ErrorKind::Error {
+                        let located = RuntimeError::new_token(paren, String::new());
+                        error.line = located.line;
+                        error.column = located.column;
+                        error.length = located.length;
+                        error.location = located.location;
+                    }
+                    error
+                }),
+            //Calling a class runs its initializer, which is the frame shown in traces
+            Class(class) => self.call_function(&class, "init", argument_vals, paren),
             _ => Err(RuntimeError::new_token(
                 paren,
                 "Can only call functions and classes.".to_string(),
@@ -303,7 +778,7 @@ impl expr::Visitor<Result<LoxValue, RuntimeError>> for Interpreter {
                 Number(val) => Ok(Number(-val)),
                 _ => Err(invalid_operand_number(operator)),
             },
-            BANG => Ok(Boolean(right.is_truthy())),
+            BANG => Ok(Boolean(!right.is_truthy())),
             _ => Err(RuntimeError::new_token(
                 operator,
                 "Missed Parser Error".to_string(),
@@ -319,40 +794,183 @@ impl expr::Visitor<Result<LoxValue, RuntimeError>> for Interpreter {
     fn visit_assign_expr(&self, name: &Token, value: Rc<Expr>, expr_pointer_id: usize) -> Result<LoxValue, RuntimeError> {
         let value = self.evaluate(value)?;
         match self.locals.borrow().get(&expr_pointer_id) {
-            Some(dist) => self
+            Some((_, dist)) => self
                 .environment
                 .borrow()
                 .borrow_mut()
                 .assign_at(*dist, name, &value)?,
             //If there is no distance it must be a global variable 
-            None => self.globals.borrow_mut().assign(name, &value)?,
+            None => self.global_environment().borrow_mut().assign(name, &value)?,
         }
         Ok(value)
     }
-}
 
-impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
-    fn visit_expression_stmt(&self, expression: Rc<Expr>) -> Result<(), RuntimeError> {
-        match self.evaluate(expression) {
-            Ok(_val) => Ok(()),
-            Err(error) => Err(error),
+    fn visit_get_expr(&self, object: Rc<Expr>, name: &Token) -> Result<LoxValue, RuntimeError> {
+        match self.evaluate(object)? {
+            Instance(instance) => instance.get(name),
+            Module(module) => module.get(name),
+            _ => Err(RuntimeError::new_token(
+                name,
+                "Only instances have properties.".to_string(),
+            )),
         }
     }
 
-    fn visit_print_stmt(&self, expression: Rc<Expr>) -> Result<(), RuntimeError> {
-        match self.evaluate(expression) {
-            Ok(val) => {
-                writeln!(self.output.borrow_mut(), "{}", val)
-                    .expect("Interpreter Buffer Write Error");
-                Ok(())
+    fn visit_set_expr(&self, object: Rc<Expr>, name: &Token, value: Rc<Expr>) -> Result<LoxValue, RuntimeError> {
+        match self.evaluate(object)? {
+            Instance(instance) => {
+                let value = self.evaluate(value)?;
+                let added = if instance.has_field(&name.lexeme) { 0 } else { 1 };
+                self.check_store(added, std::slice::from_ref(&value))
+                    .map_err(|message| RuntimeError::new_token(name, message))?;
+                instance.set(name, value.clone());
+                Ok(value)
             }
-            Err(error) => Err(error),
+            Module(_) => Err(RuntimeError::new_token(
+                name,
+                "Can't assign to a module member.".to_string(),
+            )),
+            _ => Err(RuntimeError::new_token(
+                name,
+                "Only instances have fields.".to_string(),
+            )),
         }
     }
 
-    fn visit_var_stmt(&self, name: Token, initializer: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_this_expr(&self, keyword: &Token, expr_pointer_id: usize) -> Result<LoxValue, RuntimeError> {
+        self.look_up_variable(keyword, expr_pointer_id)
+    }
+
+    fn visit_super_expr(&self, keyword: &Token, method: &Token, expr_pointer_id: usize) -> Result<LoxValue, RuntimeError> {
+        //The resolver always resolves "super", so the distance is guaranteed to exist
+        let (_, distance) = *self
+            .locals
+            .borrow()
+            .get(&expr_pointer_id)
+            .expect("Unresolved 'super' expression");
+
+        let superclass = match self.environment.borrow().borrow().get_at(distance, keyword)? {
+            Class(class) => class,
+            _ => return Err(RuntimeError::new_token(
+                keyword,
+                "Superclass must be a class.".to_string(),
+            )),
+        };
+
+        //"this" is always bound in the environment just inside the one holding "super"
+        let this = Token::new(
+            THIS,
+            "this".to_string(),
+            Nil,
+            keyword.line,
+            keyword.column,
+            keyword.offset,
+        );
+        let object = match self.environment.borrow().borrow().get_at(distance - 1, &this)? {
+            Instance(instance) => instance,
+            _ => return Err(RuntimeError::new_token(
+                keyword,
+                "'this' is not bound to an instance.".to_string(),
+            )),
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(found) => Ok(Function(Rc::new(found.bind(object)))),
+            None => Err(RuntimeError::new_token(
+                method,
+                ["Undefined property '", method.lexeme.as_str(), "'."].concat(),
+            )),
+        }
+    }
+
+    fn visit_list_expr(&self, bracket: &Token, elements: &Vec<Rc<Expr>>) -> Result<LoxValue, RuntimeError> {
+        let mut values = vec![];
+        for element in elements {
+            values.push(self.evaluate(element.clone())?);
+        }
+        self.check_store(values.len(), &values)
+            .map_err(|message| RuntimeError::new_token(bracket, message))?;
+        Ok(List(self.new_list(values)))
+    }
+
+    fn visit_map_expr(&self, brace: &Token, entries: &Vec<(Rc<Expr>, Rc<Expr>)>) -> Result<LoxValue, RuntimeError> {
+        let map = self.new_map();
+        for (key, value) in entries {
+            let key = self.evaluate(key.clone())?;
+            let value = self.evaluate(value.clone())?;
+            self.map_set(&map, key, value)
+                .map_err(|message| RuntimeError::new_token(brace, message))?;
+        }
+        Ok(Map(map))
+    }
+
+    fn visit_index_expr(&self, object: Rc<Expr>, bracket: &Token, index: Rc<Expr>) -> Result<LoxValue, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+
+        let result = match object {
+            List(list) => list.get(&index),
+            Map(map) => map.get(&index),
+            _ => Err("Only lists and maps can be indexed.".to_string()),
+        };
+        result.map_err(|message| RuntimeError::new_token(bracket, message))
+    }
+
+    fn visit_index_set_expr(&self, object: Rc<Expr>, bracket: &Token, index: Rc<Expr>, value: Rc<Expr>) -> Result<LoxValue, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+
+        let result = match object {
+            List(list) => self
+                .check_store(0, std::slice::from_ref(&value))
+                .and_then(|()| list.set(&index, value.clone())),
+            Map(map) => self.map_set(&map, index, value.clone()),
+            _ => Err("Only lists and maps can be indexed.".to_string()),
+        };
+        result.map_err(|message| RuntimeError::new_token(bracket, message))?;
+        Ok(value)
+    }
+
+    fn visit_lambda_expr(&self, keyword: &Token, params: &Vec<Token>, body: &Vec<Rc<Stmt>>) -> Result<LoxValue, RuntimeError> {
+        let name = Token::new(
+            IDENTIFIER,
+            "anonymous".to_string(),
+            Nil,
+            keyword.line,
+            keyword.column,
+            keyword.offset,
+        );
+        let func = LoxFunction {
+            arity: params.len() as u32,
+            declaration: Rc::new(Stmt::Function {
+                name,
+                params: params.clone(),
+                body: body.clone(),
+            }),
+            closure: self.environment.borrow().clone(),
+            is_initializer: false,
+        };
+        Ok(Function(Rc::new(func)))
+    }
+}
+
+impl stmt::Visitor<Result<(), Unwind>> for Interpreter {
+    fn visit_expression_stmt(&self, expression: Rc<Expr>) -> Result<(), Unwind> {
+        self.evaluate(expression)?;
+        Ok(())
+    }
+
+    fn visit_print_stmt(&self, expression: Rc<Expr>) -> Result<(), Unwind> {
+        let val = self.evaluate(expression)?;
+        writeln!(self.output.borrow_mut(), "{}", val).expect("Interpreter Buffer Write Error");
+        Ok(())
+    }
+
+    fn visit_var_stmt(&self, name: Token, initializer: Rc<Expr>) -> Result<(), Unwind> {
         //initializer can always be evaluated because if it is empty it is a literal nil expression
         let value = self.evaluate(initializer)?;
+        self.check_sandbox(&name)?;
         self.environment
             .borrow()
             .borrow_mut()
@@ -360,20 +978,29 @@ impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
         Ok(())
     }
 
-    fn visit_while_stmt(&self, condition: Rc<Expr>, body: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_while_stmt(&self, condition: Rc<Expr>, body: Rc<Stmt>, increment: &Option<Rc<Expr>>) -> Result<(), Unwind> {
         while self.evaluate(condition.clone())?.is_truthy() {
-            self.execute(body.clone())?;
+            self.tick()?;
+            match self.execute(body.clone()) {
+                Ok(()) | Err(Unwind::Continue) => (),
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment.clone())?;
+            }
         }
         Ok(())
     }
 
-    fn visit_block_stmt(&self, statements: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+    fn visit_block_stmt(&self, brace: Token, statements: &Vec<Rc<Stmt>>) -> Result<(), Unwind> {
+        self.check_sandbox(&brace)?;
         let env = Environment::new_enclosed(self.environment.borrow().clone());
         self.execute_block(statements, env)?;
         Ok(())
     }
 
-    fn visit_if_stmt(&self, condition: Rc<Expr>, then_branch: Rc<Stmt>, else_branch: &Option<Rc<Stmt>>) -> Result<(), RuntimeError>{
+    fn visit_if_stmt(&self, condition: Rc<Expr>, then_branch: Rc<Stmt>, else_branch: &Option<Rc<Stmt>>) -> Result<(), Unwind>{
         if self.evaluate(condition)?.is_truthy(){
             Ok(self.execute(then_branch)?)
         } else {
@@ -384,7 +1011,7 @@ impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
         }
     }
 
-    fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+    fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>) -> Result<(), Unwind> {
         //closure is the environment at the time of definition.
         let closure = self.environment.borrow().clone();
         let func = LoxFunction {
@@ -395,6 +1022,7 @@ impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
                 body,
             }),
             closure: closure,
+            is_initializer: false,
         };
 
         self.environment
@@ -405,14 +1033,130 @@ impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
         Ok(())
     }
 
-    //Return uses error propagation to return its value packaged in a RuntimeError
-    fn visit_return_stmt(&self, keyword: Token, value: Rc<Expr>) -> Result<(), RuntimeError> {
-        let value_eval = self.evaluate(value)?;
-        Err(RuntimeError::new_with_return(
-            &keyword,
-            "Return called outside of function".to_string(),
-            value_eval,
-        ))
+    //Return unwinds to the enclosing LoxFunction::call
+    fn visit_return_stmt(&self, _keyword: Token, value: Option<Rc<Expr>>) -> Result<(), Unwind> {
+        let value_eval = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Nil,
+        };
+        Err(Unwind::Return(value_eval))
+    }
+
+    fn visit_class_stmt(&self, name: Token, superclass: &Option<Rc<Expr>>, methods: &Vec<Rc<Stmt>>) -> Result<(), Unwind> {
+        let superclass = match superclass {
+            Some(superclass_expr) => match self.evaluate(superclass_expr.clone())? {
+                Class(class) => Some(class),
+                _ => {
+                    //The parser only accepts a name as the superclass
+                    let at = match superclass_expr.as_ref() {
+                        Expr::Variable { name } => name,
+                        _ => &name,
+                    };
+                    return Err(RuntimeError::new_token(at, "Superclass must be a class.".to_string()).into());
+                }
+            },
+            None => None,
+        };
+
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .define(name.lexeme.clone(), Nil);
+
+        //Methods of a subclass close over an extra environment that binds "super"
+        let previous = match superclass {
+            Some(ref class) => {
+                let mut environment = Environment::new_enclosed(self.environment.borrow().clone());
+                environment.define("super".to_string(), Class(class.clone()));
+                Some(self.environment.replace(Rc::new(RefCell::new(environment))))
+            }
+            None => None,
+        };
+
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            if let Stmt::Function { name: method_name, params, body: _ } = method.as_ref() {
+                let func = LoxFunction {
+                    arity: params.len() as u32,
+                    declaration: method.clone(),
+                    closure: self.environment.borrow().clone(),
+                    is_initializer: method_name.lexeme == "init",
+                };
+                class_methods.insert(method_name.lexeme.clone(), Rc::new(func));
+            }
+        }
+
+        let class = LoxClass {
+            name: name.lexeme.clone(),
+            superclass,
+            methods: class_methods,
+        };
+
+        if let Some(previous) = previous {
+            self.environment.replace(previous);
+        }
+
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .assign(&name, &Class(Rc::new(class)))?;
+        Ok(())
+    }
+
+    fn visit_break_stmt(&self, _keyword: Token) -> Result<(), Unwind> {
+        Err(Unwind::Break)
+    }
+
+    fn visit_continue_stmt(&self, _keyword: Token) -> Result<(), Unwind> {
+        Err(Unwind::Continue)
+    }
+
+    //Any value can be thrown. Error instances from a catch clause are rethrown with their original message
+    fn visit_throw_stmt(&self, keyword: Token, value: Rc<Expr>) -> Result<(), Unwind> {
+        let value = self.evaluate(value)?;
+        let message = match &value {
+            Instance(instance) if Rc::ptr_eq(&instance.class, &self.error_class) => {
+                let message = Token::synthetic(IDENTIFIER, "message", keyword.line);
+                instance.get(&message)?.to_string()
+            }
+            value => value.to_string(),
+        };
+        let mut error = RuntimeError::new_token(&keyword, message);
+        error.value = Some(Box::new(value));
+        Err(error.into())
+    }
+
+    fn visit_try_stmt(&self, keyword: Token, body: &Vec<Rc<Stmt>>, catch: &Option<(Token, Vec<Rc<Stmt>>)>, finally: &Option<Vec<Rc<Stmt>>>) -> Result<(), Unwind> {
+        self.check_sandbox(&keyword)?;
+        let enclosing = self.environment.borrow().clone();
+        let mut result = self.execute_block(body, Environment::new_enclosed(enclosing.clone()));
+
+        if let Some((name, handler)) = catch {
+            match result {
+                //Execution limits can't be caught, the script has to stop
+                Err(Unwind::Error(error)) if error.kind == ErrorKind::Error => {
+                    let mut environment = Environment::new_enclosed(enclosing.clone());
+                    environment.define(name.lexeme.clone(), self.error_value(error));
+                    result = self.execute_block(handler, environment);
+                }
+                other => result = other,
+            }
+        }
+
+        //finally runs however the other blocks ended. If it unwinds itself, that replaces their outcome
+        if let Some(finally) = finally {
+            self.execute_block(finally, Environment::new_enclosed(enclosing))?;
+        }
+        result
+    }
+
+    fn visit_import_stmt(&self, _keyword: Token, path: Token, name: Token) -> Result<(), Unwind> {
+        let module = self.import_module(&path)?;
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .define(name.lexeme, Module(module));
+        Ok(())
     }
 }
 
@@ -420,7 +1164,41 @@ impl stmt::Visitor<Result<(), RuntimeError>> for Interpreter {
 pub struct RuntimeError {
     pub message: String,
     pub line: u32,
-    pub return_value: Option<LoxValue>,
+    //0 if the error is not associated with a token
+    pub column: u32,
+    //Width of the offending token and its description, e.g. "at 'x'"
+    pub length: u32,
+    pub location: String,
+    pub notes: Vec<String>,
+    //The value passed to throw. None for errors raised by the interpreter itself.
+    //Boxed to keep RuntimeError, which is returned by almost every function, small
+    pub value: Option<Box<LoxValue>>,
+    //Call stack when the error was raised, innermost first. Filled in once the error leaves a call or the script
+    pub trace: Vec<String>,
+    pub kind: ErrorKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorKind {
+    //An ordinary runtime error, which try/catch can handle
+    Error,
+    //The step or time limit was reached. Not catchable and not associated with a line
+    ExecutionLimit,
+}
+
+//Statements signal everything that stops them early with this type.
+//Only Error is a failure, the others are control flow that is caught by the enclosing function or loop
+pub enum Unwind {
+    Return(LoxValue),
+    Break,
+    Continue,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
 }
 
 impl RuntimeError {
@@ -428,23 +1206,34 @@ impl RuntimeError {
         RuntimeError {
             message,
             line,
-            return_value: None,
+            column: 0,
+            length: 1,
+            location: String::new(),
+            notes: Vec::new(),
+            value: None,
+            trace: Vec::new(),
+            kind: ErrorKind::Error,
         }
     }
 
-    pub fn new_token(token: &Token, message: String) -> Self {
+    pub fn execution_limit(message: String) -> Self {
         RuntimeError {
-            message: ["at '", token.lexeme.as_str(), "'", message.as_str()].concat(),
-            line: token.line,
-            return_value: None,
+            kind: ErrorKind::ExecutionLimit,
+            ..RuntimeError::new(message, 0)
         }
     }
 
-    pub fn new_with_return(token: &Token, message: String, return_value: LoxValue) -> Self {
+    pub fn new_token(token: &Token, message: String) -> Self {
         RuntimeError {
-            message: ["at '", token.lexeme.as_str(), "'", message.as_str()].concat(),
+            message,
             line: token.line,
-            return_value: Some(return_value),
+            column: token.column,
+            length: token.width(),
+            location: ["at '", token.lexeme.as_str(), "'"].concat(),
+            notes: Vec::new(),
+            value: None,
+            trace: Vec::new(),
+            kind: ErrorKind::Error,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_callable::Arity;
+use crate::lox_io::LoxIo;
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+//This file defines the natives that read the console and the filesystem.
+//They all go through the interpreter's LoxIo, which the sandbox can disable
+
+pub fn define_io_natives(globals: &mut Environment) {
+    define_native(globals, "input", Arity::Range(0, 1), input);
+    define_native(globals, "read_line", 0, read_line);
+    define_native(globals, "read_file", 1, read_file);
+    define_native(globals, "write_file", 2, write_file);
+    define_native(globals, "append_file", 2, append_file);
+    define_native(globals, "file_exists", 1, file_exists);
+    define_native(globals, "list_dir", 1, list_dir);
+}
+
+fn io_for(interpreter: &Interpreter) -> Result<Rc<dyn LoxIo>, RuntimeError> {
+    interpreter.io().map_err(native_error)
+}
+
+fn expect_path<'a>(value: &'a LoxValue, function: &str) -> Result<&'a Path, RuntimeError> {
+    match value {
+        LoxValue::LoxString(path) => Ok(Path::new(path)),
+        _ => Err(native_error(
+            [function, "() expects a path string as its first argument."].concat(),
+        )),
+    }
+}
+
+fn expect_contents<'a>(value: &'a LoxValue, function: &str) -> Result<&'a str, RuntimeError> {
+    match value {
+        LoxValue::LoxString(contents) => Ok(contents),
+        _ => Err(native_error(
+            [function, "() expects a string to write."].concat(),
+        )),
+    }
+}
+
+fn io_error(error: io::Error, action: &str, path: &Path) -> RuntimeError {
+    native_error(format!(
+        "Could not {} '{}': {}.",
+        action,
+        path.display(),
+        error
+    ))
+}
+
+//Returns the line, or nil at the end of the input
+fn line_value(
+    line: io::Result<Option<String>>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    match line {
+        Ok(Some(line)) => {
+            interpreter
+                .allocate_string(line.len())
+                .map_err(native_error)?;
+            Ok(LoxValue::LoxString(line))
+        }
+        Ok(None) => Ok(LoxValue::Nil),
+        Err(error) => Err(native_error(format!("Could not read input: {}.", error))),
+    }
+}
+
+//input(prompt) shows the prompt first
+fn input(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let prompt = match arguments.first() {
+        None => String::new(),
+        Some(LoxValue::LoxString(prompt)) => prompt.clone(),
+        Some(_) => return Err(native_error("input() expects a prompt string.".to_string())),
+    };
+    let io = io_for(interpreter)?;
+    line_value(
+        io.read_line(&prompt, interpreter.string_budget()),
+        interpreter,
+    )
+}
+
+fn read_line(
+    _arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let io = io_for(interpreter)?;
+    line_value(io.read_line("", interpreter.string_budget()), interpreter)
+}
+
+fn read_file(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let path = expect_path(&arguments[0], "read_file")?;
+    let io = io_for(interpreter)?;
+    let contents = io
+        .read_file(path, interpreter.string_budget())
+        .map_err(|error| io_error(error, "read", path))?;
+    interpreter
+        .allocate_string(contents.len())
+        .map_err(native_error)?;
+    Ok(LoxValue::LoxString(contents))
+}
+
+fn write_file(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let path = expect_path(&arguments[0], "write_file")?;
+    let contents = expect_contents(&arguments[1], "write_file")?;
+    let io = io_for(interpreter)?;
+    io.write_file(path, contents)
+        .map_err(|error| io_error(error, "write", path))?;
+    Ok(LoxValue::Nil)
+}
+
+fn append_file(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let path = expect_path(&arguments[0], "append_file")?;
+    let contents = expect_contents(&arguments[1], "append_file")?;
+    let io = io_for(interpreter)?;
+    io.append_file(path, contents)
+        .map_err(|error| io_error(error, "append to", path))?;
+    Ok(LoxValue::Nil)
+}
+
+fn file_exists(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let path = expect_path(&arguments[0], "file_exists")?;
+    let io = io_for(interpreter)?;
+    Ok(LoxValue::Boolean(io.file_exists(path)))
+}
+
+//Returns a sorted list of the names in a directory
+fn list_dir(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let path = expect_path(&arguments[0], "list_dir")?;
+    let io = io_for(interpreter)?;
+    let names = io
+        .list_dir(path)
+        .map_err(|error| io_error(error, "list", path))?;
+    //Checked before any Lox values are made from the names
+    interpreter
+        .check_store(names.len(), &[])
+        .map_err(native_error)?;
+    interpreter
+        .allocate_string(names.iter().map(|name| name.len()).sum())
+        .map_err(native_error)?;
+    let elements = names.into_iter().map(LoxValue::LoxString).collect();
+    Ok(LoxValue::List(interpreter.new_list(elements)))
+}
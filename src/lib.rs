@@ -1,164 +1,359 @@
 //This is file is essentially the main file
-use crate::interpreter::Interpreter;
+use crate::compiler::Compiler;
+use crate::interpreter::{ErrorKind, Interpreter};
+use crate::lox_error::{
+    Diagnostic, LoxError, Severity, COMPILE_ERROR, LIMIT_ERROR, RESOLVE_ERROR, RUNTIME_ERROR,
+};
+use crate::lox_type::LoxValue;
 use crate::parser::Parser;
+use crate::repl::{ReplInput, CONTINUATION_PROMPT, HISTORY_FILE, PROMPT};
 use crate::resolver::Resolver;
-use crate::token::{Token, TokenType};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
+use std::io::{self, Write};
+use std::path::Path;
 use std::rc::Rc;
+use std::thread;
+use crate::vm::Vm;
+mod chunk;
+mod compiler;
 mod environment;
 mod expr;
 pub mod interpreter;
-mod lox_callable;
+mod io_natives;
+mod list_natives;
+pub mod lox_callable;
+mod lox_class;
+pub mod lox_error;
 mod lox_function;
-mod lox_type;
+mod lox_instance;
+pub mod lox_io;
+mod lox_list;
+mod lox_map;
+mod lox_module;
+pub mod lox_type;
+mod map_natives;
+mod math_natives;
 mod native_function;
 mod parser;
+mod random;
+mod random_natives;
+mod repl;
 mod resolver;
+pub mod sandbox;
 mod scanner;
+mod string_natives;
 mod stmt;
 mod token;
+mod type_natives;
+pub mod vm;
+mod vm_value;
 
 use crate::scanner::Scanner;
 
+//Native stack of the command line interpreter. Enough for about 16000 nested calls in debug builds, see --max-call-depth
+const CLI_STACK_SIZE: usize = 256 * 1024 * 1024;
+
 //Effectively the main function of the project
 pub fn entry_point() {
-    let mut lox = Lox {
-        had_error: false,
-        had_runtime_error: false,
-        interpreter: Rc::new(Interpreter::new()),
+    with_stack_size(CLI_STACK_SIZE, || {
         //Output to stdout
-        output_buffer: &mut io::stdout(),
-    };
-    lox.main();
+        let mut stdout = io::stdout();
+        let mut lox = Lox::new(&mut stdout);
+        lox.interpreter.set_native_stack_size(CLI_STACK_SIZE);
+        lox.main();
+    });
+}
+
+//Runs "f" on a new thread with a native stack of "stack_size" bytes and returns its result.
+//Deep but legitimate recursion needs a larger stack than the main thread has. Panics are passed on to the caller
+pub fn with_stack_size<T: Send>(stack_size: usize, f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, f)
+            .expect("Could not start interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+//Selects how parsed programs are executed
+pub enum Backend {
+    //Walk the AST directly with the Interpreter
+    TreeWalk,
+    //Compile to bytecode and run it on the stack-based Vm. The Vm keeps globals between runs
+    Bytecode(Vm),
+}
+
+//Controls the warnings reported by the Resolver
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WarningMode {
+    Ignore,
+    //Warnings are collected in Lox::warnings and do not stop the program
+    Report,
+    //Any warning is turned into an error and the program is not run
+    Deny,
 }
+
+//Lox is also the embedding API. Nothing reachable from eval_source or run_file exits the process or prints errors,
+//they are returned as a LoxError instead
 pub struct Lox<'a> {
     pub had_error: bool,
     pub had_runtime_error: bool,
     pub interpreter: Rc<Interpreter>,
+    pub backend: Backend,
     //Output buffer is the location to which print statements write
-    //Errors are always printed to stdout
     pub output_buffer: &'a mut dyn Write,
+    //Rendered diagnostics are written here by report. Defaults to stderr
+    pub error_buffer: Box<dyn Write + 'a>,
+    pub warning_mode: WarningMode,
+    //Warnings from the most recent run when warning_mode is Report
+    pub warnings: Vec<Diagnostic>,
+    //Lines typed into the REPL that do not form a complete program yet
+    repl_input: String,
 }
 
-fn error(line: u32, message: &String) {
-    report(line, &String::from(""), message);
+fn read_source(path: &str) -> Result<String, LoxError> {
+    fs::read_to_string(path).map_err(|error| LoxError::Io {
+        path: path.to_string(),
+        message: error.to_string(),
+    })
 }
 
-fn error_token(token: &Token, message: String) {
-    if token.kind == TokenType::EOF {
-        report(token.line, &" at end".to_string(), &message);
-    } else {
-        let location = [" at '", token.lexeme.as_str(), "'"].concat();
-        report(token.line, &location, &message);
+impl<'a> Lox<'a> {
+    //The tree-walk interpreter recurses on the native stack of the calling thread and assumes it has at least 2 MiB, the
+    //size Rust gives spawned threads. For other sizes call Interpreter::set_native_stack_size, see also with_stack_size
+    pub fn new(output_buffer: &'a mut dyn Write) -> Self {
+        Lox {
+            had_error: false,
+            had_runtime_error: false,
+            interpreter: Rc::new(Interpreter::new()),
+            backend: Backend::TreeWalk,
+            output_buffer,
+            error_buffer: Box::new(io::stderr()),
+            warning_mode: WarningMode::Ignore,
+            warnings: Vec::new(),
+            repl_input: String::new(),
+        }
     }
-}
 
-fn report(line: u32, location: &String, message: &String) {
-    println!("[line {}] Error {} : {}", line, location, message);
-}
+    //Writes every diagnostic of an error, with snippets of the source it came from, to the error buffer
+    pub fn report(&mut self, error: &LoxError, source: &str) {
+        write!(self.error_buffer, "{}", error.render(source))
+            .expect("Could not write to provided error buffer");
+    }
+
+    //Writes the warnings of the most recent run to the error buffer
+    pub fn report_warnings(&mut self, source: &str) {
+        for warning in &self.warnings {
+            write!(self.error_buffer, "{}", warning.render(source))
+                .expect("Could not write to provided error buffer");
+        }
+    }
 
-impl<'a> Lox<'a> {
     fn main(&mut self) {
-        let args: Vec<String> = env::args().collect();
-        if args.len() > 2 {
-            println!("Usage: cargo run -- [script]");
+        let mut args: Vec<String> = env::args().skip(1).collect();
+
+        //"--vm" selects the bytecode backend
+        if let Some(index) = args.iter().position(|arg| arg == "--vm") {
+            args.remove(index);
+            self.backend = Backend::Bytecode(Vm::new());
+        }
+
+        //"--warnings" reports static warnings and "--deny-warnings" turns them into errors
+        if let Some(index) = args.iter().position(|arg| arg == "--warnings") {
+            args.remove(index);
+            self.warning_mode = WarningMode::Report;
+        }
+        if let Some(index) = args.iter().position(|arg| arg == "--deny-warnings") {
+            args.remove(index);
+            self.warning_mode = WarningMode::Deny;
+        }
+
+        //"--max-call-depth N" changes the recursion limit of the tree-walk interpreter
+        if let Some(index) = args.iter().position(|arg| arg == "--max-call-depth") {
+            match args.get(index + 1).map(|depth| depth.parse::<usize>()) {
+                Some(Ok(depth)) => {
+                    self.interpreter.set_max_call_depth(depth);
+                    args.drain(index..index + 2);
+                }
+                _ => {
+                    println!("--max-call-depth expects a number.");
+                    std::process::exit(65);
+                }
+            }
+        }
+
+        if args.len() > 1 {
+            println!("Usage: cargo run -- [--vm] [--warnings | --deny-warnings] [--max-call-depth N] [script]");
             std::process::exit(65);
-        } else if args.len() == 2 {
-            self.run_file(args.get(1).unwrap());
+        } else if args.len() == 1 {
+            //The source is kept so that diagnostics can show the offending lines
+            let (source, result) = match read_source(&args[0]) {
+                Ok(source) => {
+                    self.interpreter.push_file(Path::new(&args[0]));
+                    let result = self.eval_source(&source);
+                    (source, result)
+                }
+                Err(error) => (String::new(), Err(error)),
+            };
+            self.report_warnings(&source);
+            if let Err(error) = result {
+                self.report(&error, &source);
+                std::process::exit(error.exit_code());
+            }
         } else {
             self.run_prompt();
         }
     }
-    fn error_exit(&mut self) {
-        if self.had_runtime_error {
-            std::process::exit(70);
-        }
-        if self.had_error {
-            std::process::exit(65);
-        }
+
+    //Runs a script from disk. Globals defined by the script remain available to later calls
+    pub fn run_file(&mut self, path: &str) -> Result<LoxValue, LoxError> {
+        let source = read_source(path)?;
+        //Imports in the script are resolved relative to it
+        self.interpreter.push_file(Path::new(path));
+        let result = self.eval_source(&source);
+        self.interpreter.pop_file();
+        result
     }
 
-    pub fn run_file(&mut self, path: &String) {
-        let source: String =
-            fs::read_to_string(path).expect(["Cannot find file: ", path].concat().as_str());
-        match self.run(source) {
-            Ok(()) => return,
-            Err(()) => self.error_exit(),
+    //Runs a piece of source code and returns the value of its final statement if that is an expression statement.
+    //Any other program evaluates to nil
+    pub fn eval_source(&mut self, source: &str) -> Result<LoxValue, LoxError> {
+        let result = self.run(source.to_string());
+        match &result {
+            Err(LoxError::Runtime(_)) | Err(LoxError::LimitExceeded(_)) => self.had_runtime_error = true,
+            Err(_) => self.had_error = true,
+            Ok(_) => (),
         }
+        result
     }
 
+    //Interactive mode with line editing and a history kept in HISTORY_FILE. Ctrl-C discards the current input and
+    //Ctrl-D exits
     fn run_prompt(&mut self) {
-        loop {
-            write!(self.output_buffer, ">>> ").expect("Could not write to provided output buffer");
-            io::stdout().flush().unwrap();
-
-            let stdin = io::stdin();
-            let mut line = String::new();
-            match stdin.lock().read_line(&mut line) {
-                Ok(_a) => (),
-                Err(_e) => break,
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(error) => {
+                writeln!(self.error_buffer, "Could not start the REPL: {}", error)
+                    .expect("Could not write to provided error buffer");
+                return;
             }
+        };
+        //There is no history file before the first session
+        let _ = editor.load_history(HISTORY_FILE);
 
-            //exit repl
-            if line.eq("\n") {
-                break;
+        let mut prompt = PROMPT;
+        loop {
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        let _ = editor.add_history_entry(line.as_str());
+                    }
+                    prompt = if self.repl_line(&line) { PROMPT } else { CONTINUATION_PROMPT };
+                }
+                Err(ReadlineError::Interrupted) => {
+                    self.repl_input.clear();
+                    prompt = PROMPT;
+                }
+                Err(_) => break,
             }
+        }
 
-            //do not exit repl due to error
-            match self.run(line) {
-                Ok(()) => (),
-                Err(()) => (),
-            }
+        if let Err(error) = editor.save_history(HISTORY_FILE) {
+            writeln!(self.error_buffer, "Could not save the REPL history: {}", error)
+                .expect("Could not write to provided error buffer");
+        }
+    }
 
-            self.had_error = false;
+    //Adds a line typed into the REPL. Returns false while the input so far is incomplete.
+    //Otherwise the input is run, the value of a final expression statement is written to the output buffer and
+    //errors are reported, and true is returned
+    pub fn repl_line(&mut self, line: &str) -> bool {
+        self.repl_input.push_str(line);
+        self.repl_input.push('\n');
+        let (source, echo) = match repl::classify(&self.repl_input) {
+            ReplInput::Incomplete => return false,
+            ReplInput::Ready { source, echo } => (source, echo),
+        };
+        self.repl_input.clear();
+
+        //Errors do not exit the REPL
+        let result = self.run(source.clone());
+        self.report_warnings(&source);
+        match result {
+            Ok(value) if echo => writeln!(self.output_buffer, "{}", value)
+                .expect("Could not write to provided output buffer"),
+            Ok(_) => (),
+            Err(error) => self.report(&error, &source),
         }
+
+        self.had_error = false;
+        true
     }
 
-    fn run(&mut self, source: String) -> Result<(), ()> {
+    fn run(&mut self, source: String) -> Result<LoxValue, LoxError> {
+        self.warnings.clear();
         let mut scanner = Scanner::new(source);
-
-        let tokens = match scanner.scan_tokens() {
-            Ok(tokens) => tokens,
-            Err(tokens) => {
-                self.had_error = true;
-                tokens
-            }
-        };
+        let tokens = scanner.scan_tokens().map_err(LoxError::Scan)?;
 
         let mut parser = Parser::new(tokens);
-        let statements = match parser.parse() {
-            Ok(expr) => expr,
-            Err(()) => {
-                self.had_error = true;
-                return Err(());
-            }
-        };
+        let statements = parser.parse().map_err(LoxError::Parse)?;
 
         //No need to keep the scanner or parser in memory
         drop(parser);
         drop(scanner);
 
-        let resolver = Resolver::new(self.interpreter.clone());
-        match resolver.resolve_vec(&statements) {
-            Ok(()) => (),
-            Err(error) => {
-                self.had_error = true;
-                crate::error(error.line, &error.message);
-                self.error_exit()
+        let resolver = Resolver::new(&self.interpreter);
+        resolver.declare_globals(self.interpreter.global_names());
+        let resolved = resolver.resolve_program(&statements);
+        let warnings = resolver.take_warnings();
+        drop(resolver);
+
+        resolved.map_err(|errors| {
+            let diagnostics = errors
+                .into_iter()
+                .map(|error| Diagnostic::from_runtime_error(RESOLVE_ERROR, error))
+                .collect();
+            LoxError::Resolve(diagnostics)
+        })?;
+
+        match self.warning_mode {
+            WarningMode::Ignore => (),
+            WarningMode::Report => self.warnings = warnings,
+            WarningMode::Deny if !warnings.is_empty() => {
+                let diagnostics = warnings
+                    .into_iter()
+                    .map(|warning| Diagnostic {
+                        severity: Severity::Error,
+                        ..warning
+                    })
+                    .collect();
+                return Err(LoxError::Resolve(diagnostics));
             }
+            WarningMode::Deny => (),
         }
 
-        drop(resolver);
-
-        match self.interpreter.interpret(statements, self.output_buffer) {
-            Ok(()) => Ok(()),
-            Err(error) => {
-                self.had_runtime_error = true;
-                crate::error(error.line, &error.message);
-                Err(())
+        match self.backend {
+            Backend::TreeWalk => self.interpreter.interpret(statements, self.output_buffer),
+            Backend::Bytecode(ref mut vm) => {
+                //Compile errors are static errors, like the resolver's
+                let script = Compiler::new()
+                    .compile(&statements)
+                    .map_err(|error| {
+                        LoxError::Resolve(vec![Diagnostic::from_runtime_error(COMPILE_ERROR, error)])
+                    })?;
+                vm.interpret(script, self.output_buffer).map(|value| value.to_lox_value())
             }
         }
+        .map_err(|error| match error.kind {
+            ErrorKind::Error => LoxError::Runtime(Box::new(Diagnostic::from_runtime_error(RUNTIME_ERROR, error))),
+            ErrorKind::ExecutionLimit => {
+                LoxError::LimitExceeded(Box::new(Diagnostic::from_runtime_error(LIMIT_ERROR, error)))
+            }
+        })
     }
 }
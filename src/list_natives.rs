@@ -0,0 +1,95 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_list::LoxList;
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+use std::rc::Rc;
+
+//This file defines the native functions that operate on lists
+
+pub fn define_list_natives(globals: &mut Environment) {
+    define_native(globals, "len", 1, len);
+    define_native(globals, "push", 2, push);
+    define_native(globals, "pop", 1, pop);
+    define_native(globals, "insert", 3, insert);
+    define_native(globals, "remove", 2, remove);
+    define_native(globals, "slice", 3, slice);
+}
+
+fn expect_list(value: &LoxValue, function: &str) -> Result<Rc<LoxList>, RuntimeError> {
+    match value {
+        LoxValue::List(list) => Ok(list.clone()),
+        _ => Err(native_error(
+            [function, "() expects a list as its first argument."].concat(),
+        )),
+    }
+}
+
+//len() also accepts maps and strings. Strings are measured in characters
+fn len(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    match &arguments[0] {
+        LoxValue::List(list) => Ok(LoxValue::Number(list.elements.borrow().len() as f64)),
+        LoxValue::Map(map) => Ok(LoxValue::Number(map.len() as f64)),
+        LoxValue::LoxString(string) => Ok(LoxValue::Number(string.chars().count() as f64)),
+        _ => Err(native_error(
+            "len() expects a list, a map or a string.".to_string(),
+        )),
+    }
+}
+
+fn push(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = expect_list(&arguments[0], "push")?;
+    interpreter
+        .check_store(1, &arguments[1..])
+        .map_err(native_error)?;
+    list.push(arguments[1].clone());
+    Ok(LoxValue::Nil)
+}
+
+fn pop(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = expect_list(&arguments[0], "pop")?;
+    match list.pop() {
+        Some(value) => Ok(value),
+        None => Err(native_error("Can't pop from an empty list.".to_string())),
+    }
+}
+
+fn insert(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = expect_list(&arguments[0], "insert")?;
+    let length = list.elements.borrow().len();
+    //Inserting at the length appends to the list
+    let index = LoxList::checked_index(&arguments[1], length).map_err(native_error)?;
+    interpreter
+        .check_store(1, &arguments[2..])
+        .map_err(native_error)?;
+    list.insert(index, arguments[2].clone());
+    Ok(LoxValue::Nil)
+}
+
+fn remove(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = expect_list(&arguments[0], "remove")?;
+    let length = list.elements.borrow().len();
+    if length == 0 {
+        return Err(native_error("List index out of range.".to_string()));
+    }
+    let index = LoxList::checked_index(&arguments[1], length - 1).map_err(native_error)?;
+    Ok(list.remove(index))
+}
+
+//Returns a new list holding the elements in [start, end)
+fn slice(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = expect_list(&arguments[0], "slice")?;
+    let elements = list.elements.borrow();
+    let start = LoxList::checked_index(&arguments[1], elements.len()).map_err(native_error)?;
+    let end = LoxList::checked_index(&arguments[2], elements.len()).map_err(native_error)?;
+    if start > end {
+        return Err(native_error(
+            "Slice start must not be greater than its end.".to_string(),
+        ));
+    }
+    let sliced = &elements[start..end];
+    interpreter
+        .check_store(sliced.len(), sliced)
+        .map_err(native_error)?;
+    Ok(LoxValue::List(interpreter.new_list(sliced.to_vec())))
+}
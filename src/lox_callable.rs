@@ -1,8 +1,46 @@
 use crate::interpreter::{Interpreter, RuntimeError};
 use crate::lox_type::LoxValue;
+use std::fmt;
 
 //This trait defines a callable LoxValue
 pub trait LoxCallable{
     fn call(&self, interpreter: &Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError>;
-    fn arity(&self) -> u32;
+    fn arity(&self) -> Arity;
+}
+
+//The number of arguments a callable accepts. Functions declared in Lox always have an exact arity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Arity {
+    Exact(u32),
+    //Optional parameters: from the first to the second number of arguments, inclusive
+    Range(u32, u32),
+    //Variadic: any number of arguments from this one up
+    AtLeast(u32),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(arity) => count == arity as usize,
+            Arity::Range(min, max) => (min as usize..=max as usize).contains(&count),
+            Arity::AtLeast(min) => count >= min as usize,
+        }
+    }
+}
+
+impl From<u32> for Arity {
+    fn from(arity: u32) -> Self {
+        Arity::Exact(arity)
+    }
+}
+
+//Used in "Expected _ arguments but got n."
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(arity) => write!(f, "{}", arity),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {}", min),
+        }
+    }
 }
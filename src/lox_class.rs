@@ -0,0 +1,58 @@
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_callable::{Arity, LoxCallable};
+use crate::lox_function::LoxFunction;
+use crate::lox_type::LoxValue;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+//This struct represents a class declared in Lox
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    //Methods are looked up on the class first and then up the inheritance chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => match self.superclass {
+                Some(ref superclass) => superclass.find_method(name),
+                None => None,
+            },
+        }
+    }
+}
+
+//Calling a class constructs a new instance, so the class needs a reference to itself to hand to the instance
+impl LoxCallable for Rc<LoxClass> {
+    fn arity(&self) -> Arity {
+        match self.find_method("init") {
+            Some(initializer) => initializer.arity(),
+            None => Arity::Exact(0),
+        }
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
+        let instance = interpreter.new_instance(self.clone());
+        if let Some(initializer) = self.find_method("init") {
+            initializer.bind(instance.clone()).call(interpreter, arguments)?;
+        }
+        Ok(LoxValue::Instance(instance))
+    }
+}
+
+//Equality is defined as being the same class in memory
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
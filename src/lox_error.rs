@@ -0,0 +1,248 @@
+use crate::interpreter::RuntimeError;
+use crate::token::{Token, TokenType};
+use std::fmt;
+
+//Error codes identify the stage that produced a diagnostic
+pub const IO_ERROR: &str = "E0001";
+pub const SCAN_ERROR: &str = "E0100";
+pub const PARSE_ERROR: &str = "E0200";
+pub const RESOLVE_ERROR: &str = "E0300";
+pub const COMPILE_ERROR: &str = "E0310";
+pub const RUNTIME_ERROR: &str = "E0400";
+pub const LIMIT_ERROR: &str = "E0410";
+//Warnings are reported by the Resolver
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const UNUSED_PARAMETER: &str = "W0002";
+pub const UNUSED_ASSIGNMENT: &str = "W0003";
+pub const SHADOWED_VARIABLE: &str = "W0004";
+
+//Frames shown at each end of a rendered stack trace
+const TRACE_EDGE_FRAMES: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+//A single error message with the span of source it refers to
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub line: u32,
+    //1-based column of the start of the span, 0 if unknown
+    pub column: u32,
+    //Number of columns covered by the span
+    pub length: u32,
+    //Where the error occurred, e.g. "at 'foo'" or "at end". Empty if unknown
+    pub location: String,
+    pub message: String,
+    //Extra lines of explanation printed below the snippet
+    pub notes: Vec<String>,
+    //Call stack of a runtime error, innermost first, e.g. "[line 3] in fib()"
+    pub trace: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, line: u32, column: u32, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            line,
+            column,
+            length: 1,
+            location: String::new(),
+            message,
+            notes: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn at_token(code: &'static str, token: &Token, message: String) -> Self {
+        let location = if token.kind == TokenType::EOF {
+            "at end".to_string()
+        } else {
+            ["at '", token.lexeme.as_str(), "'"].concat()
+        };
+        Diagnostic {
+            length: token.width(),
+            location,
+            ..Diagnostic::new(code, token.line, token.column, message)
+        }
+    }
+
+    pub fn warning(code: &'static str, token: &Token, message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::at_token(code, token, message)
+        }
+    }
+
+    pub fn from_runtime_error(code: &'static str, error: RuntimeError) -> Self {
+        Diagnostic {
+            length: error.length,
+            location: error.location,
+            notes: error.notes,
+            trace: error.trace,
+            ..Diagnostic::new(code, error.line, error.column, error.message)
+        }
+    }
+
+    pub fn with_length(mut self, length: u32) -> Self {
+        self.length = length;
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    //Renders the diagnostic with the offending line of source and a caret underline beneath the span:
+    //
+    //error[E0200]: Expect ';' after value.
+    // --> line 1, column 10 at 'x'
+    //  |
+    //1 | print 1 x;
+    //  |         ^
+    //
+    //Runtime errors end with their stack trace
+    pub fn render(&self, source: &str) -> String {
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = format!("{}[{}]: {}\n", label, self.code, self.message);
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        let mut position = format!("line {}", self.line);
+        if self.column > 0 {
+            position.push_str(&format!(", column {}", self.column));
+        }
+        if !self.location.is_empty() {
+            position.push(' ');
+            position.push_str(&self.location);
+        }
+        if self.line > 0 {
+            out.push_str(&format!("{}--> {}\n", gutter, position));
+        }
+
+        let source_line = match self.line {
+            0 => None,
+            line => source.lines().nth(line as usize - 1),
+        };
+        if let (Some(text), true) = (source_line, self.column > 0) {
+            //Keep tabs in the padding so the caret lines up with the source
+            let padding: String = text
+                .chars()
+                .take(self.column as usize - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", self.line, text));
+            out.push_str(&format!(
+                "{} | {}{}\n",
+                gutter,
+                padding,
+                "^".repeat(self.length.max(1) as usize)
+            ));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        //Long traces, e.g. of a stack overflow, are cut down to the innermost and outermost frames
+        let skipped = match self.trace.len().saturating_sub(2 * TRACE_EDGE_FRAMES) {
+            //Skipping a single frame would save nothing
+            1 => 0,
+            skipped => skipped,
+        };
+        for (i, frame) in self.trace.iter().enumerate() {
+            if skipped == 0 || i < TRACE_EDGE_FRAMES || i >= TRACE_EDGE_FRAMES + skipped {
+                out.push_str(&format!("{}\n", frame));
+            } else if i == TRACE_EDGE_FRAMES {
+                out.push_str(&format!("... {} more frames\n", skipped));
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        if self.location.is_empty() {
+            write!(f, "[line {}] {}: {}", self.line, label, self.message)
+        } else {
+            write!(f, "[line {}] {} {}: {}", self.line, label, self.location, self.message)
+        }
+    }
+}
+
+//This enum is returned by the embedding API. Each stage of the pipeline has its own variant
+#[derive(Clone, PartialEq, Debug)]
+pub enum LoxError {
+    //The source file could not be read
+    Io { path: String, message: String },
+    //Scanning, parsing and resolving collect every error they find before stopping
+    Scan(Vec<Diagnostic>),
+    Parse(Vec<Diagnostic>),
+    Resolve(Vec<Diagnostic>),
+    //Execution stops at the first runtime error. Boxed because runtime errors carry a stack trace
+    Runtime(Box<Diagnostic>),
+    //The step or time limit set on the Interpreter was reached
+    LimitExceeded(Box<Diagnostic>),
+}
+
+impl LoxError {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Io { path, message } => vec![Diagnostic::new(
+                IO_ERROR,
+                0,
+                0,
+                ["Cannot read file '", path.as_str(), "': ", message.as_str()].concat(),
+            )],
+            Self::Scan(diagnostics) | Self::Parse(diagnostics) | Self::Resolve(diagnostics) => {
+                diagnostics.clone()
+            }
+            Self::Runtime(diagnostic) | Self::LimitExceeded(diagnostic) => vec![diagnostic.as_ref().clone()],
+        }
+    }
+
+    //Exit code used by the command line interface, following the textbook
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Runtime(_) | Self::LimitExceeded(_) => 70,
+            _ => 65,
+        }
+    }
+
+    //Renders every diagnostic against the source that produced them
+    pub fn render(&self, source: &str) -> String {
+        self.diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.render(source))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let diagnostics = self.diagnostics();
+        for (i, diagnostic) in diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoxError {}
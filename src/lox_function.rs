@@ -1,8 +1,10 @@
 use crate::environment::Environment;
-use crate::interpreter::{Interpreter, RuntimeError};
-use crate::lox_callable::LoxCallable;
+use crate::interpreter::{Interpreter, RuntimeError, Unwind};
+use crate::lox_callable::{Arity, LoxCallable};
+use crate::lox_instance::LoxInstance;
 use crate::lox_type::LoxValue;
 use crate::stmt::Stmt;
+use crate::token::{Token, TokenType};
 use std::cell::RefCell;
 use std::fmt;
 use std::rc::Rc;
@@ -14,11 +16,43 @@ pub struct LoxFunction {
     pub arity: u32,
     pub declaration: Rc<Stmt>,
     pub closure: Rc<RefCell<Environment>>,
+    //Initializers always return "this", even when they hit an empty return
+    pub is_initializer: bool,
+}
+
+impl LoxFunction {
+    //Creates a copy of this method whose closure defines "this" as the given instance
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut environment = Environment::new_enclosed(self.closure.clone());
+        environment.define("this".to_string(), LoxValue::Instance(instance));
+        LoxFunction {
+            arity: self.arity,
+            declaration: self.declaration.clone(),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self.declaration.as_ref() {
+            Stmt::Function {
+                name,
+                params: _,
+                body: _,
+            } => name.lexeme.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn bound_this(&self) -> Result<LoxValue, RuntimeError> {
+        let this = Token::synthetic(TokenType::THIS, "this", 0);
+        self.closure.borrow().get_at(0, &this)
+    }
 }
 
 impl LoxCallable for LoxFunction {
-    fn arity(&self) -> u32 {
-        self.arity
+    fn arity(&self) -> Arity {
+        Arity::Exact(self.arity)
     }
 
     fn call(&self, interpreter: &Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError>{
@@ -39,11 +73,10 @@ impl LoxCallable for LoxFunction {
                 }
 
                 match interpreter.execute_block(&body, environment) {
-                    //Check for return packaged in a RuntimeError
-                    Err(error) => match error.return_value {
-                        Some(value) => Ok(value),
-                        None => Err(error),
-                    },
+                    Err(Unwind::Error(error)) => Err(error),
+                    _ if self.is_initializer => self.bound_this(),
+                    Err(Unwind::Return(value)) => Ok(value),
+                    //The Resolver rejects break and continue outside of loops
                     _ => Ok(LoxValue::Nil),
                 }
             }
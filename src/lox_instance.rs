@@ -0,0 +1,86 @@
+use crate::environment::EnvironmentStats;
+use crate::interpreter::RuntimeError;
+use crate::lox_class::LoxClass;
+use crate::lox_type::{drop_values, LoxValue};
+use crate::token::Token;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+//This struct represents an instance of a Lox class.
+//Instances are shared by reference so the fields are stored in a RefCell to allow mutation through an Rc
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: RefCell<HashMap<String, LoxValue>>,
+    //Every field counts as a live value, like a variable does
+    stats: Option<Rc<EnvironmentStats>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        LoxInstance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+            stats: None,
+        }
+    }
+
+    //An instance whose fields are counted in "stats"
+    pub fn new_tracked(class: Rc<LoxClass>, stats: Rc<EnvironmentStats>) -> Self {
+        LoxInstance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+            stats: Some(stats),
+        }
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.borrow().contains_key(name)
+    }
+
+    //Fields shadow methods. Methods are bound to this instance before they are returned
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<LoxValue, RuntimeError> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match self.class.find_method(&name.lexeme) {
+            Some(method) => Ok(LoxValue::Function(Rc::new(method.bind(self.clone())))),
+            None => Err(RuntimeError::new_token(
+                name,
+                ["Undefined property '", name.lexeme.as_str(), "'."].concat(),
+            )),
+        }
+    }
+
+    pub fn set(&self, name: &Token, value: LoxValue) {
+        let replaced = self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+        if let (None, Some(stats)) = (replaced, &self.stats) {
+            stats.values.set(stats.values.get() + 1);
+        }
+    }
+}
+
+impl Drop for LoxInstance {
+    fn drop(&mut self) {
+        let fields = std::mem::take(self.fields.get_mut());
+        if let Some(stats) = &self.stats {
+            stats.values.set(stats.values.get() - fields.len());
+        }
+        drop_values(fields.into_values());
+    }
+}
+
+//Equality is defined as being the same instance in memory
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+//This trait is how the I/O natives reach the console and the filesystem.
+//Interpreter::set_io replaces it, e.g. with a MemoryIo in tests. Methods take &self like the rest of the interpreter
+//Reads take a "max_bytes" budget from the sandbox's string limit. They stop early once the text is longer than that,
+//returning what they have, so an oversized input is never held in full; the caller then reports the limit error
+pub trait LoxIo {
+    //Shows the prompt and reads one line without its line ending. None at the end of the input
+    fn read_line(&self, prompt: &str, max_bytes: usize) -> io::Result<Option<String>>;
+    fn read_file(&self, path: &Path, max_bytes: usize) -> io::Result<String>;
+    fn write_file(&self, path: &Path, contents: &str) -> io::Result<()>;
+    fn append_file(&self, path: &Path, contents: &str) -> io::Result<()>;
+    fn file_exists(&self, path: &Path) -> bool;
+    //The names of the entries in a directory, sorted
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<String>>;
+}
+
+//The real console and filesystem. Relative paths are relative to the working directory
+pub struct StdIo;
+
+impl LoxIo for StdIo {
+    fn read_line(&self, prompt: &str, max_bytes: usize) -> io::Result<Option<String>> {
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", prompt)?;
+        stdout.flush()?;
+
+        let mut bytes = Vec::new();
+        //Leaves room for a "\r\n" line ending after the longest allowed line
+        let limit = max_bytes.saturating_add(3) as u64;
+        if io::stdin()
+            .lock()
+            .take(limit)
+            .read_until(b'\n', &mut bytes)?
+            == 0
+        {
+            return Ok(None);
+        }
+        let length = bytes.len()
+            - bytes
+                .iter()
+                .rev()
+                .take_while(|b| matches!(b, b'\n' | b'\r'))
+                .count();
+        bytes.truncate(length);
+        decode(bytes, max_bytes).map(Some)
+    }
+
+    fn read_file(&self, path: &Path, max_bytes: usize) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        File::open(path)?
+            .take(max_bytes.saturating_add(1) as u64)
+            .read_to_end(&mut bytes)?;
+        decode(bytes, max_bytes)
+    }
+
+    fn write_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn append_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        file.write_all(contents.as_bytes())
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(path)? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+//Text over the budget may have been cut inside a character. It is decoded lossily, which never makes it shorter
+fn decode(bytes: Vec<u8>, max_bytes: usize) -> io::Result<String> {
+    if bytes.len() > max_bytes {
+        return Ok(String::from_utf8_lossy(&bytes).into_owned());
+    }
+    String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+//Returns no more of "text" than needed to show that it is over the budget
+fn truncate(text: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes.saturating_add(1).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    text[..end].to_string()
+}
+
+//An in-memory console and filesystem. Files are stored by path exactly as the script wrote it;
+//a directory lists the files whose paths continue below it
+#[derive(Default)]
+pub struct MemoryIo {
+    pub input: RefCell<VecDeque<String>>,
+    pub files: RefCell<BTreeMap<PathBuf, String>>,
+    //Every prompt shown by read_line, in order
+    pub prompts: RefCell<String>,
+}
+
+impl MemoryIo {
+    pub fn new(input: &[&str]) -> Self {
+        MemoryIo {
+            input: RefCell::new(input.iter().map(|line| line.to_string()).collect()),
+            ..MemoryIo::default()
+        }
+    }
+
+    fn not_found(path: &Path) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        )
+    }
+}
+
+impl LoxIo for MemoryIo {
+    fn read_line(&self, prompt: &str, max_bytes: usize) -> io::Result<Option<String>> {
+        self.prompts.borrow_mut().push_str(prompt);
+        let line = self.input.borrow_mut().pop_front();
+        Ok(line.map(|line| truncate(&line, max_bytes)))
+    }
+
+    fn read_file(&self, path: &Path, max_bytes: usize) -> io::Result<String> {
+        self.files
+            .borrow()
+            .get(path)
+            .map(|contents| truncate(contents, max_bytes))
+            .ok_or_else(|| MemoryIo::not_found(path))
+    }
+
+    fn write_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files
+            .borrow_mut()
+            .insert(path.to_path_buf(), contents.to_string());
+        Ok(())
+    }
+
+    fn append_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files
+            .borrow_mut()
+            .entry(path.to_path_buf())
+            .or_default()
+            .push_str(contents);
+        Ok(())
+    }
+
+    fn file_exists(&self, path: &Path) -> bool {
+        self.files.borrow().contains_key(path)
+            || !self.list_dir(path).unwrap_or_default().is_empty()
+    }
+
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        //Relative paths are stored without a leading "./"
+        let path = if path == Path::new(".") {
+            Path::new("")
+        } else {
+            path
+        };
+        let mut names: Vec<String> = Vec::new();
+        for file in self.files.borrow().keys() {
+            if let Ok(rest) = file.strip_prefix(path) {
+                if let Some(name) = rest.iter().next() {
+                    let name = name.to_string_lossy().into_owned();
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        if names.is_empty() {
+            return Err(MemoryIo::not_found(path));
+        }
+        names.sort();
+        Ok(names)
+    }
+}
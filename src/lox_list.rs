@@ -0,0 +1,143 @@
+use crate::environment::EnvironmentStats;
+use crate::lox_type::{drop_values, format_nested, LoxValue};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+//This struct represents a Lox list.
+//Lists are shared by reference, so the elements are stored in a RefCell to allow mutation through an Rc.
+//Add and remove elements with the methods below rather than through "elements", so the sandbox's count stays right
+pub struct LoxList {
+    pub elements: RefCell<Vec<LoxValue>>,
+    //Set while the list is being formatted so that a list containing itself does not recurse forever
+    formatting: Cell<bool>,
+    //Every element counts as a live value, like a variable does
+    stats: Option<Rc<EnvironmentStats>>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<LoxValue>) -> Self {
+        LoxList {
+            elements: RefCell::new(elements),
+            formatting: Cell::new(false),
+            stats: None,
+        }
+    }
+
+    //A list whose elements are counted in "stats"
+    pub fn new_tracked(elements: Vec<LoxValue>, stats: Rc<EnvironmentStats>) -> Self {
+        stats.values.set(stats.values.get() + elements.len());
+        LoxList {
+            elements: RefCell::new(elements),
+            formatting: Cell::new(false),
+            stats: Some(stats),
+        }
+    }
+
+    fn track(&self, change: impl FnOnce(usize) -> usize) {
+        if let Some(stats) = &self.stats {
+            stats.values.set(change(stats.values.get()));
+        }
+    }
+
+    pub fn push(&self, value: LoxValue) {
+        self.elements.borrow_mut().push(value);
+        self.track(|values| values + 1);
+    }
+
+    pub fn pop(&self) -> Option<LoxValue> {
+        let popped = self.elements.borrow_mut().pop();
+        if popped.is_some() {
+            self.track(|values| values - 1);
+        }
+        popped
+    }
+
+    //"index" must be at most the length
+    pub fn insert(&self, index: usize, value: LoxValue) {
+        self.elements.borrow_mut().insert(index, value);
+        self.track(|values| values + 1);
+    }
+
+    //"index" must be less than the length
+    pub fn remove(&self, index: usize) -> LoxValue {
+        let removed = self.elements.borrow_mut().remove(index);
+        self.track(|values| values - 1);
+        removed
+    }
+
+    //Converts a Lox value into a list index no larger than "upper"
+    //Indexing an element uses upper = len - 1, while inserting allows upper = len
+    pub fn checked_index(index: &LoxValue, upper: usize) -> Result<usize, String> {
+        match index {
+            LoxValue::Number(number) if number.fract() == 0.0 => {
+                if *number < 0.0 || *number > upper as f64 {
+                    Err("List index out of range.".to_string())
+                } else {
+                    Ok(*number as usize)
+                }
+            }
+            _ => Err("List index must be an integer.".to_string()),
+        }
+    }
+
+    pub fn get(&self, index: &LoxValue) -> Result<LoxValue, String> {
+        let elements = self.elements.borrow();
+        if elements.is_empty() {
+            return Err("List index out of range.".to_string());
+        }
+        let index = LoxList::checked_index(index, elements.len() - 1)?;
+        Ok(elements[index].clone())
+    }
+
+    pub fn set(&self, index: &LoxValue, value: LoxValue) -> Result<(), String> {
+        let mut elements = self.elements.borrow_mut();
+        if elements.is_empty() {
+            return Err("List index out of range.".to_string());
+        }
+        let index = LoxList::checked_index(index, elements.len() - 1)?;
+        elements[index] = value;
+        Ok(())
+    }
+}
+
+impl Drop for LoxList {
+    fn drop(&mut self) {
+        let elements = std::mem::take(self.elements.get_mut());
+        self.track(|values| values - elements.len());
+        drop_values(elements);
+    }
+}
+
+//Equality is defined as being the same list in memory
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.formatting.get() {
+            return write!(f, "[...]");
+        }
+
+        let written = format_nested(|| {
+            self.formatting.set(true);
+            let mut result = write!(f, "[");
+            for (i, element) in self.elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    result = result.and(write!(f, ", "));
+                }
+                //Strings are quoted inside lists so that ["a, b"] and ["a", "b"] can be told apart
+                result = match element {
+                    LoxValue::LoxString(string) => result.and(write!(f, "\"{}\"", string)),
+                    _ => result.and(write!(f, "{}", element)),
+                };
+            }
+            self.formatting.set(false);
+            result.and(write!(f, "]"))
+        });
+        written.unwrap_or_else(|| write!(f, "[...]"))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::environment::EnvironmentStats;
+use crate::lox_type::{drop_values, format_nested, LoxValue};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+//Only primitive values can be used as map keys. This is their hashable form
+#[derive(PartialEq, Eq, Hash)]
+enum MapKey {
+    Boolean(bool),
+    //Numbers are hashed by their bits. 0 and -0 are normalized so they refer to the same entry
+    Number(u64),
+    LoxString(String),
+    Nil,
+}
+
+impl MapKey {
+    fn from_value(value: &LoxValue) -> Result<MapKey, String> {
+        match value {
+            LoxValue::Boolean(val) => Ok(MapKey::Boolean(*val)),
+            LoxValue::Number(val) if *val == 0.0 => Ok(MapKey::Number(0.0f64.to_bits())),
+            LoxValue::Number(val) => Ok(MapKey::Number(val.to_bits())),
+            LoxValue::LoxString(val) => Ok(MapKey::LoxString(val.clone())),
+            LoxValue::Nil => Ok(MapKey::Nil),
+            _ => Err("Map keys must be strings, numbers, booleans or nil.".to_string()),
+        }
+    }
+}
+
+//This struct represents a Lox map. Entries are kept in insertion order so maps print predictably.
+//Maps are shared by reference, so the contents are stored in RefCells to allow mutation through an Rc
+pub struct LoxMap {
+    entries: RefCell<Vec<(LoxValue, LoxValue)>>,
+    //Position of each key in "entries"
+    indices: RefCell<HashMap<MapKey, usize>>,
+    //Set while the map is being formatted so that a map containing itself does not recurse forever
+    formatting: Cell<bool>,
+    //Every entry counts as two live values, its key and its value
+    stats: Option<Rc<EnvironmentStats>>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        LoxMap {
+            entries: RefCell::new(Vec::new()),
+            indices: RefCell::new(HashMap::new()),
+            formatting: Cell::new(false),
+            stats: None,
+        }
+    }
+
+    //A map whose entries are counted in "stats"
+    pub fn new_tracked(stats: Rc<EnvironmentStats>) -> Self {
+        LoxMap {
+            entries: RefCell::new(Vec::new()),
+            indices: RefCell::new(HashMap::new()),
+            formatting: Cell::new(false),
+            stats: Some(stats),
+        }
+    }
+
+    fn track(&self, change: impl FnOnce(usize) -> usize) {
+        if let Some(stats) = &self.stats {
+            stats.values.set(change(stats.values.get()));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn get(&self, key: &LoxValue) -> Result<LoxValue, String> {
+        let hashed = MapKey::from_value(key)?;
+        match self.indices.borrow().get(&hashed) {
+            Some(index) => Ok(self.entries.borrow()[*index].1.clone()),
+            None => Err(["Undefined key '", key.to_string().as_str(), "'."].concat()),
+        }
+    }
+
+    pub fn has(&self, key: &LoxValue) -> Result<bool, String> {
+        let hashed = MapKey::from_value(key)?;
+        Ok(self.indices.borrow().contains_key(&hashed))
+    }
+
+    pub fn set(&self, key: LoxValue, value: LoxValue) -> Result<(), String> {
+        let hashed = MapKey::from_value(&key)?;
+        let mut entries = self.entries.borrow_mut();
+        let mut indices = self.indices.borrow_mut();
+        match indices.get(&hashed) {
+            Some(index) => entries[*index].1 = value,
+            None => {
+                indices.insert(hashed, entries.len());
+                entries.push((key, value));
+                self.track(|values| values + 2);
+            }
+        }
+        Ok(())
+    }
+
+    //Returns whether the key was present
+    pub fn delete(&self, key: &LoxValue) -> Result<bool, String> {
+        let hashed = MapKey::from_value(key)?;
+        let mut indices = self.indices.borrow_mut();
+        let removed = match indices.remove(&hashed) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+
+        self.entries.borrow_mut().remove(removed);
+        self.track(|values| values - 2);
+        //Entries after the removed one have shifted down by one
+        for index in indices.values_mut() {
+            if *index > removed {
+                *index -= 1;
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn keys(&self) -> Vec<LoxValue> {
+        self.entries.borrow().iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<LoxValue> {
+        self.entries.borrow().iter().map(|(_, value)| value.clone()).collect()
+    }
+}
+
+impl Drop for LoxMap {
+    fn drop(&mut self) {
+        let entries = std::mem::take(self.entries.get_mut());
+        self.track(|values| values - 2 * entries.len());
+        //Keys are primitives, so only the values can hold other containers
+        drop_values(entries.into_iter().map(|(_, value)| value));
+    }
+}
+
+//Equality is defined as being the same map in memory
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+//Strings are quoted so that keys and values can be told apart, e.g. {"a": "b"}
+fn write_quoted(f: &mut fmt::Formatter<'_>, value: &LoxValue) -> fmt::Result {
+    match value {
+        LoxValue::LoxString(string) => write!(f, "\"{}\"", string),
+        _ => write!(f, "{}", value),
+    }
+}
+
+impl fmt::Debug for LoxMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.formatting.get() {
+            return write!(f, "{{...}}");
+        }
+
+        let written = format_nested(|| {
+            self.formatting.set(true);
+            let mut result = write!(f, "{{");
+            for (i, (key, value)) in self.entries.borrow().iter().enumerate() {
+                if i > 0 {
+                    result = result.and(write!(f, ", "));
+                }
+                result = result
+                    .and(write_quoted(f, key))
+                    .and(write!(f, ": "))
+                    .and(write_quoted(f, value));
+            }
+            self.formatting.set(false);
+            result.and(write!(f, "}}"))
+        });
+        written.unwrap_or_else(|| write!(f, "{{...}}"))
+    }
+}
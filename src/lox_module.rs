@@ -0,0 +1,57 @@
+use crate::environment::Environment;
+use crate::interpreter::RuntimeError;
+use crate::lox_type::LoxValue;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+//This struct represents an imported file. Its members are the top-level definitions left in its environment
+pub struct LoxModule {
+    //File stem of the module's path, used when printing
+    pub name: String,
+    pub path: PathBuf,
+    pub environment: Rc<RefCell<Environment>>,
+    //The Interpreter identifies resolved expressions by address, so the AST must outlive the module's functions
+    _statements: Vec<Rc<Stmt>>,
+}
+
+impl LoxModule {
+    pub fn new(path: PathBuf, environment: Rc<RefCell<Environment>>, statements: Vec<Rc<Stmt>>) -> Self {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        LoxModule {
+            name,
+            path,
+            environment,
+            _statements: statements,
+        }
+    }
+
+    //Only the module's own definitions are members, natives are not reachable through a module
+    pub fn get(&self, name: &Token) -> Result<LoxValue, RuntimeError> {
+        match self.environment.borrow().get_own(&name.lexeme) {
+            Some(value) => Ok(value),
+            None => Err(RuntimeError::new_token(
+                name,
+                ["Undefined member '", name.lexeme.as_str(), "' in module '", self.name.as_str(), "'."].concat(),
+            )),
+        }
+    }
+}
+
+impl PartialEq for LoxModule {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
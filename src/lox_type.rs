@@ -1,7 +1,13 @@
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::rc::Rc;
 
+use crate::lox_class::LoxClass;
 use crate::lox_function::LoxFunction;
+use crate::lox_instance::LoxInstance;
+use crate::lox_list::LoxList;
+use crate::lox_map::LoxMap;
+use crate::lox_module::LoxModule;
 use crate::native_function::NativeFunction;
 
 //This enum represents all possible values in Lox. They must be enumerated as Rust is statically typed.
@@ -13,6 +19,46 @@ pub enum LoxValue {
     Nil,
     Function(Rc<LoxFunction>),
     Native(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    List(Rc<LoxList>),
+    Map(Rc<LoxMap>),
+    Module(Rc<LoxModule>),
+}
+
+//Lists, maps and instances can be nested arbitrarily deep, but formatting and dropping them recurses once per level.
+//Formatting stops at this depth and dropping goes through a queue so that neither can overflow the native stack
+const MAX_FORMAT_DEPTH: usize = 64;
+
+thread_local! {
+    static FORMAT_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static DROP_QUEUE: RefCell<Vec<LoxValue>> = const { RefCell::new(Vec::new()) };
+    static DROPPING: Cell<bool> = const { Cell::new(false) };
+}
+
+//Runs "write" one level deeper, or returns None if the value is nested too deeply to be printed
+pub(crate) fn format_nested(write: impl FnOnce() -> fmt::Result) -> Option<fmt::Result> {
+    let depth = FORMAT_DEPTH.with(|depth| depth.get());
+    if depth >= MAX_FORMAT_DEPTH {
+        return None;
+    }
+    FORMAT_DEPTH.with(|cell| cell.set(depth + 1));
+    let result = write();
+    FORMAT_DEPTH.with(|cell| cell.set(depth));
+    Some(result)
+}
+
+//Called by containers as they are dropped. The values are queued, and only the outermost call drops them, one at a
+//time, so a container that held the last reference to another only adds its contents to the queue
+pub(crate) fn drop_values(values: impl IntoIterator<Item = LoxValue>) {
+    let queued = DROP_QUEUE.try_with(|queue| queue.borrow_mut().extend(values));
+    if queued.is_err() || DROPPING.with(|dropping| dropping.replace(true)) {
+        return;
+    }
+    while let Some(value) = DROP_QUEUE.with(|queue| queue.borrow_mut().pop()) {
+        drop(value);
+    }
+    DROPPING.with(|dropping| dropping.set(false));
 }
 
 pub fn stringify_double(val: &f64) -> String {
@@ -33,6 +79,11 @@ impl fmt::Display for LoxValue {
             Self::Nil => write!(f, "nil"),
             Self::Function(func) => write!(f, "{:?}", func),
             Self::Native(func) => write!(f, "{:?}", func),
+            Self::Class(class) => write!(f, "{:?}", class),
+            Self::Instance(instance) => write!(f, "{:?}", instance),
+            Self::List(list) => write!(f, "{:?}", list),
+            Self::Map(map) => write!(f, "{:?}", map),
+            Self::Module(module) => write!(f, "{:?}", module),
         }
     }
 }
@@ -46,4 +97,20 @@ impl LoxValue {
             _ => true,
         }
     }
+
+    //The name returned by type_of(). Natives are functions too
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Boolean(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::LoxString(_) => "string",
+            Self::Nil => "nil",
+            Self::Function(_) | Self::Native(_) => "function",
+            Self::Class(_) => "class",
+            Self::Instance(_) => "instance",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Module(_) => "module",
+        }
+    }
 }
@@ -0,0 +1,56 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_map::LoxMap;
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+use std::rc::Rc;
+
+//This file defines the native functions that operate on maps. len() is shared with lists
+
+pub fn define_map_natives(globals: &mut Environment) {
+    define_native(globals, "keys", 1, keys);
+    define_native(globals, "values", 1, values);
+    define_native(globals, "has", 2, has);
+    define_native(globals, "delete", 2, delete);
+}
+
+fn expect_map(value: &LoxValue, function: &str) -> Result<Rc<LoxMap>, RuntimeError> {
+    match value {
+        LoxValue::Map(map) => Ok(map.clone()),
+        _ => Err(native_error(
+            [function, "() expects a map as its first argument."].concat(),
+        )),
+    }
+}
+
+//Keys are returned in insertion order
+fn keys(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let map = expect_map(&arguments[0], "keys")?;
+    let keys = map.keys();
+    interpreter
+        .check_store(keys.len(), &keys)
+        .map_err(native_error)?;
+    Ok(LoxValue::List(interpreter.new_list(keys)))
+}
+
+fn values(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let map = expect_map(&arguments[0], "values")?;
+    let values = map.values();
+    interpreter
+        .check_store(values.len(), &values)
+        .map_err(native_error)?;
+    Ok(LoxValue::List(interpreter.new_list(values)))
+}
+
+fn has(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let map = expect_map(&arguments[0], "has")?;
+    let found = map.has(&arguments[1]).map_err(native_error)?;
+    Ok(LoxValue::Boolean(found))
+}
+
+//Returns whether the key was present
+fn delete(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let map = expect_map(&arguments[0], "delete")?;
+    let deleted = map.delete(&arguments[1]).map_err(native_error)?;
+    Ok(LoxValue::Boolean(deleted))
+}
@@ -0,0 +1,77 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_callable::Arity;
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+use std::f64::consts;
+
+//This file defines the math natives and constants
+
+pub fn define_math_natives(globals: &mut Environment) {
+    globals.define("PI".to_string(), LoxValue::Number(consts::PI));
+    globals.define("E".to_string(), LoxValue::Number(consts::E));
+
+    define_native(globals, "sqrt", 1, unary("sqrt", f64::sqrt));
+    define_native(globals, "abs", 1, unary("abs", f64::abs));
+    define_native(globals, "floor", 1, unary("floor", f64::floor));
+    define_native(globals, "ceil", 1, unary("ceil", f64::ceil));
+    //Halfway cases round away from zero
+    define_native(globals, "round", 1, unary("round", f64::round));
+    define_native(globals, "sin", 1, unary("sin", f64::sin));
+    define_native(globals, "cos", 1, unary("cos", f64::cos));
+    define_native(globals, "tan", 1, unary("tan", f64::tan));
+    define_native(globals, "exp", 1, unary("exp", f64::exp));
+    //Natural logarithm
+    define_native(globals, "log", 1, unary("log", f64::ln));
+    define_native(globals, "pow", 2, binary("pow", f64::powf));
+    define_native(globals, "atan2", 2, binary("atan2", f64::atan2));
+    define_native(globals, "min", Arity::AtLeast(1), fold("min", f64::min));
+    define_native(globals, "max", Arity::AtLeast(1), fold("max", f64::max));
+}
+
+fn expect_number(value: &LoxValue, message: &str) -> Result<f64, RuntimeError> {
+    match value {
+        LoxValue::Number(number) => Ok(*number),
+        _ => Err(native_error(message.to_string())),
+    }
+}
+
+//Natives of the form f(x)
+fn unary(
+    name: &'static str,
+    operation: fn(f64) -> f64,
+) -> impl Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let message = [name, "() expects a number."].concat();
+    move |arguments, _interpreter| {
+        let x = expect_number(&arguments[0], &message)?;
+        Ok(LoxValue::Number(operation(x)))
+    }
+}
+
+//Natives of the form f(x, y)
+fn binary(
+    name: &'static str,
+    operation: fn(f64, f64) -> f64,
+) -> impl Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let message = [name, "() expects numbers."].concat();
+    move |arguments, _interpreter| {
+        let x = expect_number(&arguments[0], &message)?;
+        let y = expect_number(&arguments[1], &message)?;
+        Ok(LoxValue::Number(operation(x, y)))
+    }
+}
+
+//Variadic natives that combine all of their arguments, e.g. max(1, 5, 3)
+fn fold(
+    name: &'static str,
+    operation: fn(f64, f64) -> f64,
+) -> impl Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let message = [name, "() expects numbers."].concat();
+    move |arguments, _interpreter| {
+        let mut result = expect_number(&arguments[0], &message)?;
+        for argument in &arguments[1..] {
+            result = operation(result, expect_number(argument, &message)?);
+        }
+        Ok(LoxValue::Number(result))
+    }
+}
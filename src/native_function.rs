@@ -1,32 +1,36 @@
+use crate::environment::Environment;
 use crate::interpreter::{Interpreter, RuntimeError};
-use crate::lox_callable::LoxCallable;
+use crate::lox_callable::{Arity, LoxCallable};
 use crate::lox_type::LoxValue;
 use std::fmt;
+use std::rc::Rc;
+
+//Signature shared by all native functions. Natives may fail with a RuntimeError.
+//Natives are closures so that they can capture state, such as a host application's data
+pub type NativeFn = Box<dyn Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError>>;
 
 //This struct defines a native function for Lox -> a function written is Rust that can be invoked from Lox.
-#[derive(Clone)]
 pub struct NativeFunction {
-    pub arity: u32,
-    //The function is stored as a Rust function pointer
-    pub function: fn(Vec<LoxValue>, &Interpreter) -> LoxValue,
+    pub name: String,
+    //The interpreter checks the number of arguments against this before calling the function
+    pub arity: Arity,
+    pub function: NativeFn,
 }
 
 impl LoxCallable for NativeFunction {
-    fn arity(&self) -> u32 {
+    fn arity(&self) -> Arity {
         self.arity
     }
 
     fn call(&self, interpreter: &Interpreter, arguments: Vec<LoxValue>) -> Result<LoxValue, RuntimeError> {
-        Ok((self.function)(arguments, interpreter))   
+        (self.function)(arguments, interpreter)
     }
 }
 
-//This equality function seems to work quite well: "print clock == clock" -> true
+//Equality is defined as being the same native in memory: "print clock == clock" -> true
 impl PartialEq for NativeFunction {
-    //Right half of the && is borrowed from https://users.rust-lang.org/t/compare-function-pointers-for-equality/52339
     fn eq(&self, other: &Self) -> bool {
-        self.arity == other.arity
-            && (self.function as fn(Vec<LoxValue>, &'static _) -> _ == other.function)
+        std::ptr::eq(self, other)
     }
 }
 
@@ -35,3 +39,24 @@ impl fmt::Debug for NativeFunction {
         write!(f, "<native fn>")
     }
 }
+
+pub fn define_native(
+    environment: &mut Environment,
+    name: &str,
+    arity: impl Into<Arity>,
+    function: impl Fn(Vec<LoxValue>, &Interpreter) -> Result<LoxValue, RuntimeError> + 'static,
+) {
+    environment.define(
+        name.to_string(),
+        LoxValue::Native(Rc::new(NativeFunction {
+            name: name.to_string(),
+            arity: arity.into(),
+            function: Box::new(function),
+        })),
+    );
+}
+
+//Natives do not know where they were called from. The Interpreter fills in the line of the call
+pub fn native_error(message: String) -> RuntimeError {
+    RuntimeError::new(message, 0)
+}
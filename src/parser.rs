@@ -1,12 +1,23 @@
 use crate::expr::Expr;
+use crate::lox_error::{Diagnostic, PARSE_ERROR};
 use crate::stmt::Stmt;
 use crate::lox_type::LoxValue;
 use crate::token::{Token, TokenType::{self, *}};
 use std::rc::Rc;
+
+//Statements and expressions may only nest this deep. Parsing, resolving and running them all recurse once per
+//level, so deeper code could overflow the native stack
+const MAX_NESTING_DEPTH: usize = 100;
+
 pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     current: u32,
-    had_error: bool
+    had_error: bool,
+    errors: Vec<Diagnostic>,
+    //How many nested statements and expressions are being parsed
+    depth: usize,
+    //Set once the nesting limit is hit. The rest of the tokens are skipped and no further errors are reported
+    abandoned: bool,
 }
 
 impl<'a> Parser<'a> {
@@ -14,11 +25,14 @@ impl<'a> Parser<'a> {
         Parser {
             tokens: tokens,
             current: 0,
-            had_error: false
+            had_error: false,
+            errors: Vec::new(),
+            depth: 0,
+            abandoned: false,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Rc<Stmt>>, ()> {
+    pub fn parse(&mut self) -> Result<Vec<Rc<Stmt>>, Vec<Diagnostic>> {
         let mut statements: Vec<Rc<Stmt>> = vec![];
         while !self.is_at_end() {
             match self.declaration() {
@@ -28,7 +42,7 @@ impl<'a> Parser<'a> {
         }
 
         if self.had_error {
-            Err(())
+            Err(std::mem::take(&mut self.errors))
         } else {
             Ok(statements)
         }
@@ -36,9 +50,15 @@ impl<'a> Parser<'a> {
 
     pub fn declaration(&mut self) -> Result<Rc<Stmt>, ()> {
         let statement = {
-            if self.match_token(vec![VAR]) {
+            if self.match_token(vec![CLASS]) {
+                self.class_declaration()
+            } else if self.match_token(vec![VAR]) {
                 self.var_declaration()
-            } else if self.match_token(vec![FUN]) {
+            } else if self.match_token(vec![IMPORT]) {
+                self.import_declaration()
+            } else if self.check(FUN) && self.check_next(IDENTIFIER) {
+                //"fun" not followed by a name starts an anonymous function expression
+                self.advance();
                 self.function("function".to_string())
             } else {
                 self.statement()
@@ -56,6 +76,34 @@ impl<'a> Parser<'a> {
         }
     }
 
+    pub fn class_declaration(&mut self) -> Result<Rc<Stmt>, ()> {
+        let name = self.consume(IDENTIFIER, "Expect class name.".to_string())?;
+
+        let superclass = if self.match_token(vec![LESS]) {
+            let superclass_name = self.consume(IDENTIFIER, "Expect superclass name.".to_string())?;
+            Some(Rc::new(Expr::Variable {
+                name: superclass_name,
+            }))
+        } else {
+            None
+        };
+
+        self.consume(LEFT_BRACE, "Expect '{' before class body.".to_string())?;
+
+        let mut methods = vec![];
+        while !self.check(RIGHT_BRACE) && !self.is_at_end() {
+            methods.push(self.function("method".to_string())?);
+        }
+
+        self.consume(RIGHT_BRACE, "Expect '}' after class body.".to_string())?;
+
+        Ok(Rc::new(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        }))
+    }
+
     pub fn var_declaration(&mut self) -> Result<Rc<Stmt>, ()> {
         let name = match self.consume(IDENTIFIER, "Expect variable name.".to_string()) {
             Ok(identifier) => identifier,
@@ -85,6 +133,21 @@ impl<'a> Parser<'a> {
         }
     }
 
+    //import "path/to/file.lox" as name;
+    pub fn import_declaration(&mut self) -> Result<Rc<Stmt>, ()> {
+        let keyword = self.previous();
+        let path = self.consume(STRING, "Expect module path after 'import'.".to_string())?;
+        //'as' is only special here, so programs may still use it as a name
+        if !(self.check(IDENTIFIER) && self.peek().lexeme == "as") {
+            self.error_at_current("Expect 'as' after module path.".to_string());
+            return Err(());
+        }
+        self.advance();
+        let name = self.consume(IDENTIFIER, "Expect module name.".to_string())?;
+        self.consume(SEMICOLON, "Expect ';' after import.".to_string())?;
+        Ok(Rc::new(Stmt::Import { keyword, path, name }))
+    }
+
     pub fn while_statement(&mut self) -> Result<Rc<Stmt>, ()> {
         self.consume(LEFT_PAREN, "Expect '(' after 'while'.".to_string())?;
 
@@ -97,10 +160,18 @@ impl<'a> Parser<'a> {
 
         let body = self.statement()?;
 
-        Ok(Rc::new(Stmt::While { condition, body }))
+        Ok(Rc::new(Stmt::While {
+            condition,
+            body,
+            increment: None,
+        }))
     }
 
     pub fn statement(&mut self) -> Result<Rc<Stmt>, ()> {
+        self.nested(|parser| parser.parse_statement())
+    }
+
+    fn parse_statement(&mut self) -> Result<Rc<Stmt>, ()> {
         if self.match_token(vec![FOR]) {
             return self.for_statement();
         }
@@ -116,9 +187,29 @@ impl<'a> Parser<'a> {
         if self.match_token(vec![WHILE]) {
             return self.while_statement();
         }
+        if self.match_token(vec![BREAK]) {
+            let keyword = self.previous();
+            self.consume(SEMICOLON, "Expect ';' after 'break'.".to_string())?;
+            return Ok(Rc::new(Stmt::Break { keyword }));
+        }
+        if self.match_token(vec![CONTINUE]) {
+            let keyword = self.previous();
+            self.consume(SEMICOLON, "Expect ';' after 'continue'.".to_string())?;
+            return Ok(Rc::new(Stmt::Continue { keyword }));
+        }
+        if self.match_token(vec![THROW]) {
+            let keyword = self.previous();
+            let value = self.expression()?;
+            self.consume(SEMICOLON, "Expect ';' after thrown value.".to_string())?;
+            return Ok(Rc::new(Stmt::Throw { keyword, value }));
+        }
+        if self.match_token(vec![TRY]) {
+            return self.try_statement();
+        }
         if self.match_token(vec![LEFT_BRACE]) {
+            let brace = self.previous();
             match self.block() {
-                Ok(statements) => return Ok(Rc::new(Stmt::Block { statements })),
+                Ok(statements) => return Ok(Rc::new(Stmt::Block { brace, statements })),
                 Err(()) => return Err(()),
             }
         }
@@ -126,7 +217,38 @@ impl<'a> Parser<'a> {
         self.expression_statement()
     }
 
+    //try { ... } catch (name) { ... } finally { ... }
+    fn try_statement(&mut self) -> Result<Rc<Stmt>, ()> {
+        let keyword = self.previous();
+        self.consume(LEFT_BRACE, "Expect '{' after 'try'.".to_string())?;
+        let body = self.block()?;
+
+        let catch = if self.match_token(vec![CATCH]) {
+            self.consume(LEFT_PAREN, "Expect '(' after 'catch'.".to_string())?;
+            let name = self.consume(IDENTIFIER, "Expect error name.".to_string())?;
+            self.consume(RIGHT_PAREN, "Expect ')' after error name.".to_string())?;
+            self.consume(LEFT_BRACE, "Expect '{' before catch body.".to_string())?;
+            Some((name, self.block()?))
+        } else {
+            None
+        };
+
+        let finally = if self.match_token(vec![FINALLY]) {
+            self.consume(LEFT_BRACE, "Expect '{' after 'finally'.".to_string())?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            self.error_at_current("Expect 'catch' or 'finally' after try block.".to_string());
+            return Err(());
+        }
+        Ok(Rc::new(Stmt::Try { keyword, body, catch, finally }))
+    }
+
     fn for_statement(&mut self) -> Result<Rc<Stmt>, ()> {
+        let keyword = self.previous();
         self.consume(LEFT_PAREN, "Expect '(' after 'for'.".to_string())?;
 
         let initializer = if self.match_token(vec![SEMICOLON]) {
@@ -159,13 +281,6 @@ impl<'a> Parser<'a> {
 
         let mut body = self.statement()?;
 
-        body = match increment {
-            Some(expr) => Rc::new(Stmt::Block {
-                statements: vec![body, Rc::new(Stmt::Expression { expression: expr })],
-            }),
-            None => body,
-        };
-
         let condition_expr = match condition {
             Some(expr) => expr,
             None => Rc::new(Expr::Literal {
@@ -173,13 +288,16 @@ impl<'a> Parser<'a> {
             }),
         };
 
+        //The increment is kept separate from the body so that "continue" still runs it
         body = Rc::new(Stmt::While {
             condition: condition_expr,
             body,
+            increment,
         });
 
         body = match initializer {
             Some(stmt) => Rc::new(Stmt::Block {
+                brace: keyword,
                 statements: vec![stmt, body],
             }),
             None => body,
@@ -257,11 +375,9 @@ impl<'a> Parser<'a> {
         let keyword = self.previous();
         let value = {
             if !self.check(SEMICOLON) {
-                self.expression()?
+                Some(self.expression()?)
             } else {
-                Rc::new(Expr::Literal {
-                    value: LoxValue::Nil,
-                })
+                None
             }
         };
 
@@ -273,17 +389,20 @@ impl<'a> Parser<'a> {
     pub fn function(&mut self, kind: String) -> Result<Rc<Stmt>, ()>{   
         let name = self.consume(IDENTIFIER, ["Expect ".to_string() , kind.clone(), " name.".to_string()].concat())?;
         self.consume(LEFT_PAREN, ["Expect '(' after ".to_string() , kind.clone(), " name.".to_string()].concat())?;
-    
+        let (params, body) = self.function_body(kind)?;
+
+        Ok(Rc::new(Stmt::Function { name, params, body }))
+    }
+
+    //Parses the parameters and body of a function, starting after the '('
+    fn function_body(&mut self, kind: String) -> Result<(Vec<Token>, Vec<Rc<Stmt>>), ()> {
         let mut params = vec![];
         if !self.check(RIGHT_PAREN) {
             //This is a strange way to emulate a do-while loop from: https://gist.github.com/huonw/8435502
             //It works because the conditional of a while loop can be any expression, including a block expression
             while {
                 if params.len() >= 255 {
-                    crate::error_token(
-                        &self.peek(),
-                        "Can't have more than 255 parameters.".to_string(),
-                    );
+                    self.error_at_current("Can't have more than 255 parameters.".to_string());
                     return Err(());
                 }
                 params.push(self.consume(IDENTIFIER, "Expect parameter name.".to_string())?);
@@ -298,11 +417,11 @@ impl<'a> Parser<'a> {
         )?;
         let body = self.block()?;
 
-        Ok(Rc::new(Stmt::Function { name, params, body }))
+        Ok((params, body))
     }
 
     fn expression(&mut self) -> Result<Rc<Expr>, ()> {
-        self.assignment()
+        self.nested(|parser| parser.assignment())
     }
 
     fn assignment(&mut self) -> Result<Rc<Expr>, ()> {
@@ -310,7 +429,7 @@ impl<'a> Parser<'a> {
 
         if self.match_token(vec![EQUAL]) {
             let equals = self.previous();
-            let value = self.assignment()?;
+            let value = self.nested(|parser| parser.assignment())?;
 
             match expr.as_ref() {
                 //Make sure left side is L-value
@@ -318,8 +437,19 @@ impl<'a> Parser<'a> {
                     name: name.clone(),
                     value,
                 })),
+                Expr::Get { object, name } => Ok(Rc::new(Expr::Set {
+                    object: object.clone(),
+                    name: name.clone(),
+                    value,
+                })),
+                Expr::Index { object, bracket, index } => Ok(Rc::new(Expr::IndexSet {
+                    object: object.clone(),
+                    bracket: bracket.clone(),
+                    index: index.clone(),
+                    value,
+                })),
                 _ => {
-                    crate::error_token(&equals, "Invalid assignment target.".to_string());
+                    self.error(&equals, "Invalid assignment target.".to_string());
                     Err(())
                 }
             }
@@ -424,7 +554,7 @@ impl<'a> Parser<'a> {
     fn unary(&mut self) -> Result<Rc<Expr>, ()> {
         if self.match_token(vec![BANG, MINUS]) {
             let operator = self.previous();
-            let right = self.unary()?;
+            let right = self.nested(|parser| parser.unary())?;
             return Ok(Rc::new(Expr::Unary { operator, right }));
         }
 
@@ -437,6 +567,17 @@ impl<'a> Parser<'a> {
         loop {
             if self.match_token(vec![LEFT_PAREN]) {
                 expr = self.finish_call(expr)?;
+            } else if self.match_token(vec![DOT]) {
+                let name = self.consume(IDENTIFIER, "Expect property name after '.'.".to_string())?;
+                expr = Rc::new(Expr::Get { object: expr, name });
+            } else if self.match_token(vec![LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self.consume(RIGHT_BRACKET, "Expect ']' after index.".to_string())?;
+                expr = Rc::new(Expr::Index {
+                    object: expr,
+                    bracket,
+                    index,
+                });
             } else {
                 break;
             }
@@ -452,10 +593,7 @@ impl<'a> Parser<'a> {
             while {
                 arguments.push(self.expression()?);
                 if arguments.len() >= 255 {
-                    crate::error_token(
-                        &self.peek(),
-                        "Can't have more than 255 arguments.".to_string(),
-                    );
+                    self.error_at_current("Can't have more than 255 arguments.".to_string());
                     return Err(());
                 };
                 self.match_token(vec![COMMA])
@@ -475,6 +613,16 @@ impl<'a> Parser<'a> {
     }
 
     fn primary(&mut self) -> Result<Rc<Expr>, ()> {
+        if self.match_token(vec![FUN]) {
+            let keyword = self.previous();
+            self.consume(LEFT_PAREN, "Expect '(' after 'fun'.".to_string())?;
+            let (params, body) = self.function_body("function".to_string())?;
+            return Ok(Rc::new(Expr::Lambda {
+                keyword,
+                params,
+                body,
+            }));
+        }
         if self.match_token(vec![FALSE]) {
             return Ok(Rc::new(Expr::Literal {
                 value: LoxValue::Boolean(false),
@@ -497,12 +645,57 @@ impl<'a> Parser<'a> {
             }));
         }
 
+        if self.match_token(vec![SUPER]) {
+            let keyword = self.previous();
+            self.consume(DOT, "Expect '.' after 'super'.".to_string())?;
+            let method = self.consume(IDENTIFIER, "Expect superclass method name.".to_string())?;
+            return Ok(Rc::new(Expr::Super { keyword, method }));
+        }
+
+        if self.match_token(vec![THIS]) {
+            return Ok(Rc::new(Expr::This {
+                keyword: self.previous(),
+            }));
+        }
+
         if self.match_token(vec![IDENTIFIER]) {
             return Ok(Rc::new(Expr::Variable {
                 name: self.previous(),
             }));
         }
 
+        if self.match_token(vec![LEFT_BRACKET]) {
+            let bracket = self.previous();
+            let mut elements = vec![];
+            if !self.check(RIGHT_BRACKET) {
+                //The following is a do-while loop
+                while {
+                    elements.push(self.expression()?);
+                    self.match_token(vec![COMMA])
+                } {}
+            }
+            self.consume(RIGHT_BRACKET, "Expect ']' after list elements.".to_string())?;
+            return Ok(Rc::new(Expr::List { bracket, elements }));
+        }
+
+        //Statements starting with '{' are blocks, so map literals only appear in expression position
+        if self.match_token(vec![LEFT_BRACE]) {
+            let brace = self.previous();
+            let mut entries = vec![];
+            if !self.check(RIGHT_BRACE) {
+                //The following is a do-while loop
+                while {
+                    let key = self.expression()?;
+                    self.consume(COLON, "Expect ':' after map key.".to_string())?;
+                    let value = self.expression()?;
+                    entries.push((key, value));
+                    self.match_token(vec![COMMA])
+                } {}
+            }
+            self.consume(RIGHT_BRACE, "Expect '}' after map entries.".to_string())?;
+            return Ok(Rc::new(Expr::Map { brace, entries }));
+        }
+
         if self.match_token(vec![LEFT_PAREN]) {
             let expr = self.expression()?;
             if let Err(()) = self.consume(RIGHT_PAREN, "Expect ')' after expression".to_string()) {
@@ -513,7 +706,7 @@ impl<'a> Parser<'a> {
         }
 
         //No expression matched
-        crate::error_token(&self.peek(), "Expect Expression".to_string()); // report error
+        self.error_at_current("Expect Expression".to_string()); // report error
         Err(())
     }
 
@@ -527,6 +720,14 @@ impl<'a> Parser<'a> {
         false
     }
 
+    //Looks one token past the current one
+    fn check_next(&mut self, token_type: TokenType) -> bool {
+        match self.tokens.get(self.current as usize + 1) {
+            Some(token) => token.kind == token_type,
+            None => false,
+        }
+    }
+
     fn check(&mut self, token_type: TokenType) -> bool {
         if self.is_at_end() {
             return false;
@@ -560,11 +761,37 @@ impl<'a> Parser<'a> {
         if self.check(token_type) {
             Ok(self.advance())
         } else {
-            crate::error_token(&self.peek(), message);
+            self.error_at_current(message);
             Err(())
         }
     }
 
+    //Parses one level deeper, or reports an error and gives up on the rest of the source past the nesting limit
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, ()>) -> Result<T, ()> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.error_at_current("Code is nested too deeply.".to_string());
+            self.abandoned = true;
+            self.current = (self.tokens.len() - 1) as u32;
+            return Err(());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn error(&mut self, token: &Token, message: String) {
+        if self.abandoned {
+            return;
+        }
+        self.errors.push(Diagnostic::at_token(PARSE_ERROR, token, message));
+    }
+
+    fn error_at_current(&mut self, message: String) {
+        let token = self.peek();
+        self.error(&token, message);
+    }
+
     //See error reporting in readme for a discussion on issues with this function 
     pub fn synchronize(&mut self) {
         self.advance();
@@ -574,7 +801,7 @@ impl<'a> Parser<'a> {
             }
 
             match self.peek().kind {
-                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN =>  return,
+                CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN | BREAK | CONTINUE | IMPORT | THROW | TRY =>  return,
                 _ => (),
             }
             
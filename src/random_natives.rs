@@ -0,0 +1,70 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+
+//This file defines the natives that use the interpreter's pseudo-random number generator
+
+//Integers beyond this can't all be represented by a Lox number
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+pub fn define_random_natives(globals: &mut Environment) {
+    define_native(globals, "random", 0, random);
+    define_native(globals, "random_int", 2, random_int);
+    define_native(globals, "shuffle", 1, shuffle);
+    define_native(globals, "seed", 1, seed);
+}
+
+fn expect_integer(value: &LoxValue, message: &str) -> Result<f64, RuntimeError> {
+    match value {
+        LoxValue::Number(number) if number.fract() == 0.0 && number.abs() <= MAX_SAFE_INTEGER => {
+            Ok(*number)
+        }
+        _ => Err(native_error(message.to_string())),
+    }
+}
+
+//Returns a number in [0, 1)
+fn random(_arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    Ok(LoxValue::Number(interpreter.rng().next_f64()))
+}
+
+//Returns an integer in [low, high], both ends included
+fn random_int(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let message = "random_int() expects two integers.";
+    let low = expect_integer(&arguments[0], message)?;
+    let high = expect_integer(&arguments[1], message)?;
+    if low > high {
+        return Err(native_error(
+            "random_int() expects its lower bound not to be greater than its upper bound."
+                .to_string(),
+        ));
+    }
+    let offset = interpreter.rng().below((high - low) as u64 + 1);
+    Ok(LoxValue::Number(low + offset as f64))
+}
+
+//Shuffles a list in place (Fisher-Yates)
+fn shuffle(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let list = match &arguments[0] {
+        LoxValue::List(list) => list,
+        _ => return Err(native_error("shuffle() expects a list.".to_string())),
+    };
+    let mut elements = list.elements.borrow_mut();
+    let mut rng = interpreter.rng();
+    for i in (1..elements.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        elements.swap(i, j);
+    }
+    Ok(LoxValue::Nil)
+}
+
+//Restarts the generator. The same seed always produces the same numbers
+fn seed(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let seed = expect_integer(&arguments[0], "seed() expects an integer.")?;
+    interpreter.set_random_seed(seed as i64 as u64);
+    Ok(LoxValue::Nil)
+}
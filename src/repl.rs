@@ -0,0 +1,79 @@
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+
+//This file decides when the lines typed into the REPL form a program that can be run
+
+//File in the working directory that keeps the REPL's history between sessions
+pub const HISTORY_FILE: &str = ".lox_history";
+
+pub const PROMPT: &str = ">>> ";
+//Shown while the input so far is incomplete
+pub const CONTINUATION_PROMPT: &str = "... ";
+
+#[derive(PartialEq, Debug)]
+pub enum ReplInput {
+    //The input ends in the middle of a string, a bracketed construct or a statement. More lines are needed
+    Incomplete,
+    //The input can be run. "echo" is set when the final statement is an expression whose value should be shown
+    Ready { source: String, echo: bool },
+}
+
+pub fn classify(source: &str) -> ReplInput {
+    match parse(source) {
+        Parsed::Complete { echo } => ReplInput::Ready {
+            source: source.to_string(),
+            echo,
+        },
+        Parsed::Invalid => ReplInput::Ready {
+            source: source.to_string(),
+            echo: false,
+        },
+        Parsed::EndsEarly => {
+            //A bare expression doesn't need its ';'
+            let terminated = [source, ";"].concat();
+            match parse(&terminated) {
+                Parsed::Complete { echo: true } => ReplInput::Ready {
+                    source: terminated,
+                    echo: true,
+                },
+                _ => ReplInput::Incomplete,
+            }
+        }
+    }
+}
+
+enum Parsed {
+    Complete { echo: bool },
+    //Every error is at the end of the input, e.g. an unclosed brace, an unterminated string or a missing ';'
+    EndsEarly,
+    //Errors are reported when the input is run
+    Invalid,
+}
+
+fn parse(source: &str) -> Parsed {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens,
+        //Strings may span lines
+        Err(errors)
+            if errors
+                .iter()
+                .all(|error| error.message == "Unterminated string.") =>
+        {
+            return Parsed::EndsEarly;
+        }
+        Err(_) => return Parsed::Invalid,
+    };
+
+    match Parser::new(tokens).parse() {
+        Ok(statements) => Parsed::Complete {
+            echo: matches!(
+                statements.last().map(|statement| statement.as_ref()),
+                Some(Stmt::Expression { .. })
+            ),
+        },
+        Err(errors) if errors.iter().all(|error| error.location == "at end") => Parsed::EndsEarly,
+        Err(_) => Parsed::Invalid,
+    }
+}
@@ -1,37 +1,142 @@
 use crate::expr::{self, Expr};
 use crate::interpreter::Interpreter;
 use crate::interpreter::RuntimeError;
+use crate::lox_error::{
+    Diagnostic, SHADOWED_VARIABLE, UNUSED_ASSIGNMENT, UNUSED_PARAMETER, UNUSED_VARIABLE,
+};
 use crate::stmt::{self, Stmt};
 use crate::token::Token;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-pub struct Resolver {
-    interpreter: Rc<Interpreter>,
-    scopes: Rc<RefCell<Vec<HashMap<String, bool>>>>,
+//Tracks whether the resolver is inside a class body so misuse of "this" and "super" can be reported
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
 }
 
-impl Resolver {
-    pub fn new(interpreter: Rc<Interpreter>) -> Self {
+//Tracks the kind of function being resolved so misuse of "return" can be reported
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+//Implicit variables such as "this" and "super" are never reported as unused
+#[derive(Clone, Copy, PartialEq)]
+enum VariableKind {
+    Local,
+    Parameter,
+    Implicit,
+}
+
+//What the resolver knows about a local variable. Used for the initializer check and for warnings
+struct Variable {
+    //Token of the declaration, warnings point at it
+    name: Token,
+    kind: VariableKind,
+    defined: bool,
+    read: bool,
+    //The last assignment, if it has not been read since
+    unread_assignment: Option<Token>,
+    //Loop and function nesting at the declaration. Assignments made deeper than this may be read by a later
+    //iteration or call, so they are not reported as unread
+    repeat_depth: usize,
+}
+
+pub struct Resolver<'a> {
+    interpreter: &'a Interpreter,
+    scopes: Rc<RefCell<Vec<HashMap<String, Variable>>>>,
+    //Names declared at the top level of the program. Globals are not kept in scopes, but locals can shadow them
+    globals: RefCell<HashSet<String>>,
+    current_class: RefCell<ClassType>,
+    current_function: RefCell<FunctionType>,
+    //Number of loops and functions enclosing the code being resolved
+    repeat_depth: Cell<usize>,
+    //Number of loops enclosing the code being resolved within the current function
+    loop_depth: Cell<usize>,
+    //Every static error found. Resolution continues after an error so that all of them are reported in one pass
+    errors: RefCell<Vec<RuntimeError>>,
+    warnings: RefCell<Vec<Diagnostic>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a Interpreter) -> Self {
         Resolver {
             interpreter,
             scopes: Rc::new(RefCell::new(Vec::new())),
+            globals: RefCell::new(HashSet::new()),
+            current_class: RefCell::new(ClassType::None),
+            current_function: RefCell::new(FunctionType::None),
+            repeat_depth: Cell::new(0),
+            loop_depth: Cell::new(0),
+            errors: RefCell::new(Vec::new()),
+            warnings: RefCell::new(Vec::new()),
         }
     }
 
-    fn resolve(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn resolve(&self, statement: Rc<Stmt>) {
         statement.accept_stmt(self, statement.clone())
     }
 
-    pub fn resolve_vec(&self, statements: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+    //Globals defined before this program, e.g. by earlier REPL lines, which locals may shadow as well
+    pub fn declare_globals(&self, names: Vec<String>) {
+        self.globals.borrow_mut().extend(names);
+    }
+
+    //Resolves a whole program, returning every error found
+    pub fn resolve_program(&self, statements: &Vec<Rc<Stmt>>) -> Result<(), Vec<RuntimeError>> {
+        //Collected up front, since a function body may be resolved before a global declared below it
+        for statement in statements {
+            let name = match statement.as_ref() {
+                Stmt::Var { name, .. }
+                | Stmt::Function { name, .. }
+                | Stmt::Class { name, .. }
+                | Stmt::Import { name, .. } => name,
+                _ => continue,
+            };
+            self.globals.borrow_mut().insert(name.lexeme.clone());
+        }
+        self.resolve_vec(statements);
+        let errors = self.errors.take();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    //Warnings found by resolve_program, in source order
+    pub fn take_warnings(&self) -> Vec<Diagnostic> {
+        let mut warnings = self.warnings.take();
+        warnings.sort_by_key(|warning| (warning.line, warning.column));
+        warnings
+    }
+
+    fn resolve_vec(&self, statements: &Vec<Rc<Stmt>>) {
         for statement in statements {
-            self.resolve(statement.clone())?;
+            self.resolve(statement.clone());
         }
-        Ok(())
     }
 
-    fn resolve_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn error(&self, token: &Token, message: &str) {
+        self.errors
+            .borrow_mut()
+            .push(RuntimeError::new_token(token, message.to_string()));
+    }
+
+    fn warning(&self, code: &'static str, token: &Token, message: String) {
+        self.warnings
+            .borrow_mut()
+            .push(Diagnostic::warning(code, token, message));
+    }
+
+    fn resolve_expr(&self, expr: Rc<Expr>) {
         expr.accept_expr(self, expr.clone())
     }
 
@@ -40,12 +145,44 @@ impl Resolver {
     }
 
     fn end_scope(&self) -> (){
-        self.scopes.borrow_mut().pop().expect("Resolver attempted to end nonexistent scope");
+        let scope = self.scopes.borrow_mut().pop().expect("Resolver attempted to end nonexistent scope");
+
+        //Names starting with an underscore are intentionally unused
+        for variable in scope.values() {
+            if variable.kind == VariableKind::Implicit || variable.name.lexeme.starts_with('_') {
+                continue;
+            }
+            let name = &variable.name.lexeme;
+            if !variable.read {
+                match variable.kind {
+                    VariableKind::Parameter => self.warning(
+                        UNUSED_PARAMETER,
+                        &variable.name,
+                        format!("Parameter '{}' is never used.", name),
+                    ),
+                    _ => self.warning(
+                        UNUSED_VARIABLE,
+                        &variable.name,
+                        format!("Local variable '{}' is never used.", name),
+                    ),
+                }
+            } else if let Some(assignment) = &variable.unread_assignment {
+                self.warning(
+                    UNUSED_ASSIGNMENT,
+                    assignment,
+                    format!("Value assigned to '{}' is never read.", name),
+                );
+            }
+        }
     }
 
-    fn declare(&self, name: &Token) -> Result<(), RuntimeError> {
+    fn declare(&self, name: &Token) {
+        self.declare_as(name, VariableKind::Local);
+    }
+
+    fn declare_as(&self, name: &Token, kind: VariableKind) {
         if self.scopes.borrow().is_empty() {
-            return Ok(());
+            return;
         }
         let last_scope_index = self.last_scope_index();
         //If name is in scope -> Error
@@ -56,18 +193,35 @@ impl Resolver {
             .unwrap()
             .contains_key(&name.lexeme)
         {
-            return Err(RuntimeError::new_token(
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+
+        let shadows = self.scopes.borrow()[..last_scope_index].iter().any(|scope| {
+            matches!(scope.get(&name.lexeme), Some(variable) if variable.kind != VariableKind::Implicit)
+        }) || self.globals.borrow().contains(&name.lexeme);
+        if shadows {
+            self.warning(
+                SHADOWED_VARIABLE,
                 name,
-                "Already a variable with this name in this scope".to_string(),
-            ));
+                format!("Declaration of '{}' shadows a variable in an enclosing scope.", name.lexeme),
+            );
         }
+
         //Insert name into current scope
+        let variable = Variable {
+            name: name.clone(),
+            kind,
+            defined: false,
+            read: false,
+            unread_assignment: None,
+            repeat_depth: self.repeat_depth.get(),
+        };
         self.scopes
             .borrow_mut()
             .get_mut(last_scope_index)
             .unwrap()
-            .insert(name.lexeme.clone(), false);
-        Ok(())
+            .insert(name.lexeme.clone(), variable);
     }
 
     fn define(&self, name: String) -> () {
@@ -75,11 +229,27 @@ impl Resolver {
             return;
         }
         let last_scope_index = self.last_scope_index();
+        if let Some(variable) = self.scopes.borrow_mut()[last_scope_index].get_mut(&name) {
+            variable.defined = true;
+        }
+    }
+
+    //Defines a name that is not declared by the user, such as "this" and "super"
+    fn define_implicit(&self, name: &str) -> () {
+        let last_scope_index = self.last_scope_index();
+        let variable = Variable {
+            name: Token::synthetic(crate::token::TokenType::IDENTIFIER, name, 0),
+            kind: VariableKind::Implicit,
+            defined: true,
+            read: true,
+            unread_assignment: None,
+            repeat_depth: self.repeat_depth.get(),
+        };
         self.scopes
             .borrow_mut()
             .get_mut(last_scope_index)
             .unwrap()
-            .insert(name, true);
+            .insert(name.to_string(), variable);
     }
 
     fn scopes_is_empty(&self) -> bool {
@@ -91,7 +261,8 @@ impl Resolver {
         self.scopes.borrow().len() - 1
     }
 
-    fn resolve_local(&self, expr: Rc<Expr>, name: &Token) -> () {
+    //Returns the index of the scope the name was found in, or None for globals
+    fn resolve_local(&self, expr: Rc<Expr>, name: &Token) -> Option<usize> {
         for i in (0..self.scopes.borrow().len()).rev() {
             if self
                 .scopes
@@ -102,26 +273,33 @@ impl Resolver {
             {
                 //Add resolved expression to the Interpreter's hashmap 
                 self.interpreter.resolve(expr, self.last_scope_index() - i);
-                return;
+                return Some(i);
             }
         }
+        None
     }
 
-    fn resolve_function(&self, params: &Vec<Token>, body: &Vec<Rc<Stmt>>) -> Result<(), RuntimeError> {
+    fn resolve_function(&self, params: &Vec<Token>, body: &Vec<Rc<Stmt>>, kind: FunctionType) {
+        let enclosing_function = self.current_function.replace(kind);
+        //break and continue can't reach a loop outside of the function
+        let enclosing_loop_depth = self.loop_depth.replace(0);
+        self.repeat_depth.set(self.repeat_depth.get() + 1);
         self.begin_scope();
         for param in params {
-            self.declare(param)?;
+            self.declare_as(param, VariableKind::Parameter);
             self.define(param.lexeme.clone());
         }
 
-        self.resolve_vec(body)?;
+        self.resolve_vec(body);
         self.end_scope();
-        Ok(())
+        self.repeat_depth.set(self.repeat_depth.get() - 1);
+        self.loop_depth.set(enclosing_loop_depth);
+        self.current_function.replace(enclosing_function);
     }
 }
 
-impl expr::VisitorExpr<Result<(), RuntimeError>> for Resolver {
-    fn visit_binary_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+impl expr::VisitorExpr<()> for Resolver<'_> {
+    fn visit_binary_expr(&self, expr: Rc<Expr>) {
         let (left, right) = match expr.as_ref() {
             Expr::Binary {
                 left,
@@ -131,38 +309,32 @@ impl expr::VisitorExpr<Result<(), RuntimeError>> for Resolver {
             _ => panic!(),
         };
 
-        self.resolve_expr(left.clone())?;
-        self.resolve_expr(right.clone())?;
-
-        Ok(())
+        self.resolve_expr(left.clone());
+        self.resolve_expr(right.clone());
     }
 
-    fn visit_grouping_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_grouping_expr(&self, expr: Rc<Expr>) {
         let expression = match expr.as_ref() {
             Expr::Grouping { expression } => expression,
             _ => panic!(),
         };
 
-        self.resolve_expr(expression.clone())?;
-
-        Ok(())
+        self.resolve_expr(expression.clone());
     }
 
-    fn visit_literal_expr(&self, _expr: Rc<Expr>) -> Result<(), RuntimeError> {
-        Ok(())
+    fn visit_literal_expr(&self, _expr: Rc<Expr>) {
     }
 
-    fn visit_unary_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_unary_expr(&self, expr: Rc<Expr>) {
         let right = match expr.as_ref() {
             Expr::Unary { operator: _, right } => right,
             _ => panic!(),
         };
 
-        self.resolve_expr(right.clone())?;
-        Ok(())
+        self.resolve_expr(right.clone());
     }
 
-    fn visit_variable_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_variable_expr(&self, expr: Rc<Expr>) {
         let name = match expr.as_ref() {
             Expr::Variable { name } => name,
             _ => panic!(), //never happens
@@ -170,41 +342,46 @@ impl expr::VisitorExpr<Result<(), RuntimeError>> for Resolver {
 
         if !self.scopes_is_empty() {
             //Check if variable is used in it's own initializer 
-            match self
+            let is_defined = self
                 .scopes
                 .borrow()
                 .get(self.last_scope_index())
                 .unwrap()
                 .get(&name.lexeme)
-            {
-                Some(is_defined) => {
-                    if !is_defined {
-                        return Err(RuntimeError::new(
-                            "Can't read local variable in its own initializer.".to_string(),
-                            name.line,
-                        ));
-                    }
-                }
-                None => (),
+                .map(|variable| variable.defined);
+            if is_defined == Some(false) {
+                self.error(name, "Can't read local variable in its own initializer.");
             }
         }
 
-        self.resolve_local(expr.clone(), name);
-        Ok(())
+        if let Some(scope) = self.resolve_local(expr.clone(), name) {
+            if let Some(variable) = self.scopes.borrow_mut()[scope].get_mut(&name.lexeme) {
+                variable.read = true;
+                variable.unread_assignment = None;
+            }
+        }
     }
 
-    fn visit_assign_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_assign_expr(&self, expr: Rc<Expr>) {
         let (value, name) = match expr.as_ref() {
             Expr::Assign { name, value } => (value, name),
             _ => panic!(), //this should never happen
         };
-        self.resolve_expr(value.clone())?;
-        self.resolve_local(expr.clone(), name);
-
-        Ok(())
+        self.resolve_expr(value.clone());
+        if let Some(scope) = self.resolve_local(expr.clone(), name) {
+            let repeat_depth = self.repeat_depth.get();
+            if let Some(variable) = self.scopes.borrow_mut()[scope].get_mut(&name.lexeme) {
+                //An assignment inside a loop or function may be read by the next iteration or call
+                variable.unread_assignment = if repeat_depth > variable.repeat_depth {
+                    None
+                } else {
+                    Some(name.clone())
+                };
+            }
+        }
     }
 
-    fn visit_logical_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_logical_expr(&self, expr: Rc<Expr>) {
         let (left, right) = match expr.as_ref() {
             Expr::Logical {
                 left,
@@ -213,13 +390,11 @@ impl expr::VisitorExpr<Result<(), RuntimeError>> for Resolver {
             } => (left, right),
             _ => panic!(),
         };
-        self.resolve_expr(left.clone())?;
-        self.resolve_expr(right.clone())?;
-
-        Ok(())
+        self.resolve_expr(left.clone());
+        self.resolve_expr(right.clone());
     }
 
-    fn visit_call_expr(&self, expr: Rc<Expr>) -> Result<(), RuntimeError> {
+    fn visit_call_expr(&self, expr: Rc<Expr>) {
         let (callee, arguments) = match expr.as_ref() {
             Expr::Call {
                 callee,
@@ -229,60 +404,177 @@ impl expr::VisitorExpr<Result<(), RuntimeError>> for Resolver {
             _ => panic!(),
         };
 
-        self.resolve_expr(callee.clone())?;
+        self.resolve_expr(callee.clone());
         for argument in arguments {
-            self.resolve_expr(argument.clone())?;
+            self.resolve_expr(argument.clone());
         }
+    }
+
+    fn visit_get_expr(&self, expr: Rc<Expr>) {
+        let object = match expr.as_ref() {
+            Expr::Get { object, name: _ } => object,
+            _ => panic!(),
+        };
+
+        self.resolve_expr(object.clone());
+    }
+
+    fn visit_set_expr(&self, expr: Rc<Expr>) {
+        let (object, value) = match expr.as_ref() {
+            Expr::Set {
+                object,
+                name: _,
+                value,
+            } => (object, value),
+            _ => panic!(),
+        };
 
-        Ok(())
+        self.resolve_expr(value.clone());
+        self.resolve_expr(object.clone());
+    }
+
+    fn visit_this_expr(&self, expr: Rc<Expr>) {
+        let keyword = match expr.as_ref() {
+            Expr::This { keyword } => keyword,
+            _ => panic!(),
+        };
+
+        if *self.current_class.borrow() == ClassType::None {
+            self.error(keyword, "Can't use 'this' outside of a class.");
+            return;
+        }
+
+        self.resolve_local(expr.clone(), keyword);
+    }
+
+    fn visit_super_expr(&self, expr: Rc<Expr>) {
+        let keyword = match expr.as_ref() {
+            Expr::Super { keyword, method: _ } => keyword,
+            _ => panic!(),
+        };
+
+        match *self.current_class.borrow() {
+            ClassType::None => {
+                self.error(keyword, "Can't use 'super' outside of a class.");
+                return;
+            }
+            ClassType::Class => {
+                self.error(keyword, "Can't use 'super' in a class with no superclass.");
+                return;
+            }
+            ClassType::Subclass => (),
+        }
+
+        self.resolve_local(expr.clone(), keyword);
+    }
+
+    fn visit_list_expr(&self, expr: Rc<Expr>) {
+        let elements = match expr.as_ref() {
+            Expr::List { elements, .. } => elements,
+            _ => panic!(),
+        };
+
+        for element in elements {
+            self.resolve_expr(element.clone());
+        }
+    }
+
+    fn visit_map_expr(&self, expr: Rc<Expr>) {
+        let entries = match expr.as_ref() {
+            Expr::Map { brace: _, entries } => entries,
+            _ => panic!(),
+        };
+
+        for (key, value) in entries {
+            self.resolve_expr(key.clone());
+            self.resolve_expr(value.clone());
+        }
+    }
+
+    fn visit_index_expr(&self, expr: Rc<Expr>) {
+        let (object, index) = match expr.as_ref() {
+            Expr::Index {
+                object,
+                bracket: _,
+                index,
+            } => (object, index),
+            _ => panic!(),
+        };
+
+        self.resolve_expr(object.clone());
+        self.resolve_expr(index.clone());
+    }
+
+    fn visit_index_set_expr(&self, expr: Rc<Expr>) {
+        let (object, index, value) = match expr.as_ref() {
+            Expr::IndexSet {
+                object,
+                bracket: _,
+                index,
+                value,
+            } => (object, index, value),
+            _ => panic!(),
+        };
+
+        self.resolve_expr(object.clone());
+        self.resolve_expr(index.clone());
+        self.resolve_expr(value.clone());
+    }
+
+    fn visit_lambda_expr(&self, expr: Rc<Expr>) {
+        let (params, body) = match expr.as_ref() {
+            Expr::Lambda {
+                keyword: _,
+                params,
+                body,
+            } => (params, body),
+            _ => panic!(),
+        };
+
+        self.resolve_function(params, body, FunctionType::Function);
     }
 }
 
-impl stmt::VisitorStmt<Result<(), RuntimeError>> for Resolver {
-    fn visit_expression_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+impl stmt::VisitorStmt<()> for Resolver<'_> {
+    fn visit_expression_stmt(&self, statement: Rc<Stmt>) {
         let expression = match statement.as_ref() {
             Stmt::Expression { expression } => expression,
             _ => panic!(),
         };
 
-        self.resolve_expr(expression.clone())?;
-        Ok(())
+        self.resolve_expr(expression.clone());
     }
 
-    fn visit_print_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_print_stmt(&self, statement: Rc<Stmt>) {
         let expression = match statement.as_ref() {
             Stmt::Print { expression } => expression,
             _ => panic!(),
         };
 
-        self.resolve_expr(expression.clone())?;
-
-        Ok(())
+        self.resolve_expr(expression.clone());
     }
 
-    fn visit_var_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_var_stmt(&self, statement: Rc<Stmt>) {
         let (name, initializer) = match statement.as_ref() {
             Stmt::Var { name, initializer } => (name, initializer),
             _ => panic!(),
         };
-        self.declare(name)?;
-        self.resolve_expr(initializer.clone())?;
+        self.declare(name);
+        self.resolve_expr(initializer.clone());
         self.define(name.lexeme.clone());
-        Ok(())
     }
 
-    fn visit_block_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_block_stmt(&self, statement: Rc<Stmt>) {
         let statements = match statement.as_ref() {
-            Stmt::Block { statements } => statements,
+            Stmt::Block { statements, .. } => statements,
             _ => panic!(),
         };
         self.begin_scope();
-        self.resolve_vec(statements)?;
+        self.resolve_vec(statements);
         self.end_scope();
-        Ok(())
     }
 
-    fn visit_if_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_if_stmt(&self, statement: Rc<Stmt>) {
         let (condition, then_branch, else_branch) = match statement.as_ref() {
             Stmt::If {
                 condition,
@@ -292,49 +584,191 @@ impl stmt::VisitorStmt<Result<(), RuntimeError>> for Resolver {
             _ => panic!(),
         };
 
-        self.resolve_expr(condition.clone())?;
-        self.resolve(then_branch.clone())?;
+        self.resolve_expr(condition.clone());
+        self.resolve(then_branch.clone());
         match else_branch {
-            Some(else_stmt) => self.resolve(else_stmt.clone())?,
+            Some(else_stmt) => self.resolve(else_stmt.clone()),
             None => (),
         };
-
-        Ok(())
     }
 
-    fn visit_while_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
-        let (condition, body) = match statement.as_ref() {
-            Stmt::While { condition, body } => (condition, body),
+    fn visit_while_stmt(&self, statement: Rc<Stmt>) {
+        let (condition, body, increment) = match statement.as_ref() {
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => (condition, body, increment),
             _ => panic!(),
         };
 
-        self.resolve_expr(condition.clone())?;
-        self.resolve(body.clone())?;
-
-        Ok(())
+        self.repeat_depth.set(self.repeat_depth.get() + 1);
+        self.loop_depth.set(self.loop_depth.get() + 1);
+        self.resolve_expr(condition.clone());
+        self.resolve(body.clone());
+        if let Some(increment) = increment {
+            self.resolve_expr(increment.clone());
+        }
+        self.loop_depth.set(self.loop_depth.get() - 1);
+        self.repeat_depth.set(self.repeat_depth.get() - 1);
     }
 
-    fn visit_function_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
+    fn visit_function_stmt(&self, statement: Rc<Stmt>) {
         let (name, params, body) = match statement.as_ref() {
             Stmt::Function { name, params, body } => (name, params, body),
             _ => panic!(),
         };
 
-        self.declare(name)?;
+        self.declare(name);
         self.define(name.lexeme.clone());
 
-        self.resolve_function(params, body)?;
+        self.resolve_function(params, body, FunctionType::Function);
+    }
 
-        Ok(())
+    fn visit_return_stmt(&self, statement: Rc<Stmt>) {
+        let (keyword, value) = match statement.as_ref() {
+            Stmt::Return { keyword, value } => (keyword, value),
+            _ => panic!(),
+        };
+
+        match *self.current_function.borrow() {
+            FunctionType::None => self.error(keyword, "Can't return from top-level code."),
+            FunctionType::Initializer if value.is_some() => {
+                self.error(keyword, "Can't return a value from an initializer.")
+            }
+            _ => (),
+        }
+
+        if let Some(value) = value {
+            self.resolve_expr(value.clone());
+        }
     }
 
-    fn visit_return_stmt(&self, statement: Rc<Stmt>) -> Result<(), RuntimeError> {
-        let expression = match statement.as_ref() {
-            Stmt::Return { keyword: _, value } => value,
+    fn visit_class_stmt(&self, statement: Rc<Stmt>) {
+        let (name, superclass, methods) = match statement.as_ref() {
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => (name, superclass, methods),
+            _ => panic!(),
+        };
+
+        let enclosing_class = self.current_class.replace(ClassType::Class);
+
+        self.declare(name);
+        self.define(name.lexeme.clone());
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable { name: superclass_name } = superclass.as_ref() {
+                if superclass_name.lexeme == name.lexeme {
+                    self.error(superclass_name, "A class can't inherit from itself.");
+                }
+            }
+
+            self.current_class.replace(ClassType::Subclass);
+            self.resolve_expr(superclass.clone());
+
+            self.begin_scope();
+            self.define_implicit("super");
+        }
+
+        self.begin_scope();
+        self.define_implicit("this");
+
+        for method in methods {
+            if let Stmt::Function { name, params, body } = method.as_ref() {
+                let kind = if name.lexeme == "init" {
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::Method
+                };
+                self.resolve_function(params, body, kind);
+            }
+        }
+
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class.replace(enclosing_class);
+    }
+
+    fn visit_break_stmt(&self, statement: Rc<Stmt>) {
+        let keyword = match statement.as_ref() {
+            Stmt::Break { keyword } => keyword,
             _ => panic!(),
         };
 
-        self.resolve_expr(expression.clone())?;
-        Ok(())
+        if self.loop_depth.get() == 0 {
+            self.error(keyword, "Can't use 'break' outside of a loop.");
+        }
+    }
+
+    fn visit_continue_stmt(&self, statement: Rc<Stmt>) {
+        let keyword = match statement.as_ref() {
+            Stmt::Continue { keyword } => keyword,
+            _ => panic!(),
+        };
+
+        if self.loop_depth.get() == 0 {
+            self.error(keyword, "Can't use 'continue' outside of a loop.");
+        }
+    }
+
+    //The imported file is resolved separately when the import runs. Here the module name is just a variable
+    fn visit_import_stmt(&self, statement: Rc<Stmt>) {
+        let name = match statement.as_ref() {
+            Stmt::Import {
+                keyword: _,
+                path: _,
+                name,
+            } => name,
+            _ => panic!(),
+        };
+
+        self.declare(name);
+        self.define(name.lexeme.clone());
+    }
+
+    fn visit_throw_stmt(&self, statement: Rc<Stmt>) {
+        let value = match statement.as_ref() {
+            Stmt::Throw { keyword: _, value } => value,
+            _ => panic!(),
+        };
+
+        self.resolve_expr(value.clone());
+    }
+
+    //Each block gets its own scope. The caught error is bound in the scope of the catch block
+    fn visit_try_stmt(&self, statement: Rc<Stmt>) {
+        let (body, catch, finally) = match statement.as_ref() {
+            Stmt::Try {
+                keyword: _,
+                body,
+                catch,
+                finally,
+            } => (body, catch, finally),
+            _ => panic!(),
+        };
+
+        self.begin_scope();
+        self.resolve_vec(body);
+        self.end_scope();
+
+        if let Some((name, handler)) = catch {
+            self.begin_scope();
+            self.declare_as(name, VariableKind::Parameter);
+            self.define(name.lexeme.clone());
+            self.resolve_vec(handler);
+            self.end_scope();
+        }
+
+        if let Some(finally) = finally {
+            self.begin_scope();
+            self.resolve_vec(finally);
+            self.end_scope();
+        }
     }
 }
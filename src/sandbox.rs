@@ -0,0 +1,16 @@
+//Limits for running untrusted scripts, set with Interpreter::set_sandbox.
+//Exceeding a limit, or using denied I/O, raises an ordinary runtime error, which the script can catch. None leaves a resource unlimited
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SandboxConfig {
+    //Total bytes of the strings created by a single run, including the copies stored in lists and maps
+    pub max_string_bytes: Option<usize>,
+    //Environments alive at once. Every call and block creates one, and closures keep theirs alive
+    pub max_environments: Option<usize>,
+    //Values alive at once: variables in every environment, including the natives, list elements, map keys and values
+    //and instance fields
+    pub max_values: Option<usize>,
+    //Lowers the interpreter's max call depth while the sandbox is set
+    pub max_call_depth: Option<usize>,
+    //Makes the console and file natives raise errors instead of using the interpreter's LoxIo
+    pub deny_io: bool,
+}
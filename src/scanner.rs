@@ -1,8 +1,10 @@
 use crate::{
+    lox_error::{Diagnostic, SCAN_ERROR},
     lox_type::LoxValue,
     token::TokenType::*,
     token::{Token, TokenType},
 };
+use std::cell::Cell;
 use std::collections::HashMap;
 pub struct Scanner {
     source: String,
@@ -10,7 +12,12 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: u32,
+    //Byte offset of the first character of the current line, used to compute columns
+    line_start: usize,
+    //Byte offset and column of the last column computed, so that long lines are only counted once
+    last_column: Cell<(usize, u32)>,
     keywords: HashMap<String, TokenType>,
+    errors: Vec<Diagnostic>,
 }
 
 fn is_digit(c: char) -> bool {
@@ -29,19 +36,26 @@ impl Scanner {
     pub fn new(source: String) -> Self {
         let mut keywords = HashMap::new();
         keywords.insert("and".to_string(), AND);
+        keywords.insert("break".to_string(), BREAK);
+        keywords.insert("catch".to_string(), CATCH);
         keywords.insert("class".to_string(), CLASS);
+        keywords.insert("continue".to_string(), CONTINUE);
         keywords.insert("else".to_string(), ELSE);
         keywords.insert("false".to_string(), FALSE);
+        keywords.insert("finally".to_string(), FINALLY);
         keywords.insert("for".to_string(), FOR);
         keywords.insert("fun".to_string(), FUN);
         keywords.insert("if".to_string(), IF);
+        keywords.insert("import".to_string(), IMPORT);
         keywords.insert("nil".to_string(), NIL);
         keywords.insert("or".to_string(), OR);
         keywords.insert("print".to_string(), PRINT);
         keywords.insert("return".to_string(), RETURN);
         keywords.insert("super".to_string(), SUPER);
         keywords.insert("this".to_string(), THIS);
+        keywords.insert("throw".to_string(), THROW);
         keywords.insert("true".to_string(), TRUE);
+        keywords.insert("try".to_string(), TRY);
         keywords.insert("var".to_string(), VAR);
         keywords.insert("while".to_string(), WHILE);
 
@@ -51,19 +65,59 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            last_column: Cell::new((0, 1)),
             keywords,
+            errors: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, &Vec<Token>> {
+    //Scans the whole source, reporting every error found rather than stopping at the first
+    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, Vec<Diagnostic>> {
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token();
         }
 
-        self.tokens
-            .push(Token::new(EOF, String::from(""), LoxValue::Nil, self.line));
-        Ok(&self.tokens)
+        self.start = self.current;
+        self.tokens.push(Token::new(
+            EOF,
+            String::from(""),
+            LoxValue::Nil,
+            self.line,
+            self.column(),
+            self.start,
+        ));
+
+        if self.errors.is_empty() {
+            Ok(&self.tokens)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    //1-based column of the start of the current lexeme
+    fn column(&self) -> u32 {
+        self.column_at(self.line_start, self.start)
+    }
+
+    //Columns count characters rather than bytes so that carets line up under non-ASCII text.
+    //The scanner steps through bytes, so "offset" may be inside a character; only the bytes that start one are counted
+    //Offsets are asked for in order, so counting continues from the last one when it is on the same line
+    fn column_at(&self, line_start: usize, offset: usize) -> u32 {
+        let (from, column) = match self.last_column.get() {
+            (last, column) if last >= line_start && last <= offset => (last, column),
+            _ => (line_start, 1),
+        };
+        let bytes = &self.source.as_bytes()[from..offset];
+        let column = column + bytes.iter().filter(|byte| (*byte & 0xC0) != 0x80).count() as u32;
+        self.last_column.set((offset, column));
+        column
+    }
+
+    fn error(&mut self, message: &str) {
+        let diagnostic = Diagnostic::new(SCAN_ERROR, self.line, self.column(), message.to_string());
+        self.errors.push(diagnostic);
     }
 
     fn scan_token(&mut self) {
@@ -73,6 +127,9 @@ impl Scanner {
             ')' => self.add_token_null(RIGHT_PAREN),
             '{' => self.add_token_null(LEFT_BRACE),
             '}' => self.add_token_null(RIGHT_BRACE),
+            '[' => self.add_token_null(LEFT_BRACKET),
+            ']' => self.add_token_null(RIGHT_BRACKET),
+            ':' => self.add_token_null(COLON),
             ',' => self.add_token_null(COMMA),
             '.' => self.add_token_null(DOT),
             '-' => self.add_token_null(MINUS),
@@ -126,6 +183,7 @@ impl Scanner {
 
             '\n' => {
                 self.line += 1;
+                self.line_start = self.current;
             }
 
             '"' => self.add_string(),
@@ -136,7 +194,7 @@ impl Scanner {
                 } else if is_alpha(c) {
                     self.add_identifier();
                 } else {
-                    crate::error(self.line, &"Unexpected character.".to_string());
+                    self.error("Unexpected character.");
                 }
             }
         };
@@ -159,7 +217,8 @@ impl Scanner {
 
     fn add_token(&mut self, kind: TokenType, literal: LoxValue) {
         let text = self.source[self.start..self.current].to_string();
-        self.tokens.push(Token::new(kind, text, literal, self.line));
+        let column = self.column();
+        self.tokens.push(Token::new(kind, text, literal, self.line, column, self.start));
     }
 
     fn match_char(&mut self, expected: char) -> bool {
@@ -196,15 +255,30 @@ impl Scanner {
 
     fn add_string(&mut self) {
         //Read until closing " is found 
+        //Multi-line strings are reported at the line and column they start on
+        let start_line = self.line;
+        let start_line_start = self.line_start;
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
                 self.line += 1;
+                self.line_start = self.current + 1;
             }
             self.advance();
         }
 
         if self.is_at_end() {
-            crate::error(self.line, &"Unterminated string.".to_string());
+            //Underline the rest of the line the string starts on
+            let rest = &self.source[self.start..];
+            let length = rest.lines().next().unwrap_or("").chars().count();
+            let diagnostic = Diagnostic::new(
+                SCAN_ERROR,
+                start_line,
+                self.column_at(start_line_start, self.start),
+                "Unterminated string.".to_string(),
+            )
+            .with_length(length as u32)
+            .with_note("The string is never closed with '\"' before the end of the file.");
+            self.errors.push(diagnostic);
             return;
         }
 
@@ -212,7 +286,16 @@ impl Scanner {
         self.advance();
 
         let value = self.source[self.start + 1..self.current - 1].to_string();
-        self.add_token(STRING, LoxValue::LoxString(value));
+        let text = self.source[self.start..self.current].to_string();
+        let column = self.column_at(start_line_start, self.start);
+        self.tokens.push(Token::new(
+            STRING,
+            text,
+            LoxValue::LoxString(value),
+            start_line,
+            column,
+            self.start,
+        ));
     }
 
     fn add_number(&mut self) {
@@ -1,15 +1,26 @@
 use crate::{expr::Expr, token::Token};
 use std::rc::Rc;
+#[derive(PartialEq)]
 pub enum Stmt {
     Expression{expression: Rc<Expr>}, 
     Print{expression: Rc<Expr>},
     Var{name : Token, initializer: Rc<Expr>},
-    Block{statements: Vec<Rc<Stmt>>},
+    //brace is the '{' that opened the block, or the "for" keyword of a desugared loop. Sandbox errors are reported there
+    Block{brace: Token, statements: Vec<Rc<Stmt>>},
     If{condition: Rc<Expr>, then_branch: Rc<Stmt>, else_branch: Option<Rc<Stmt>>},
-    While{condition: Rc<Expr>, body: Rc<Stmt>},
+    //increment is the third clause of a desugared for loop. It runs after the body, even when the body continues
+    While{condition: Rc<Expr>, body: Rc<Stmt>, increment: Option<Rc<Expr>>},
     Function{name: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>},
-    Return{keyword: Token, value: Rc<Expr>}
-    
+    //value is None for a bare "return;"
+    Return{keyword: Token, value: Option<Rc<Expr>>},
+    Class{name: Token, superclass: Option<Rc<Expr>>, methods: Vec<Rc<Stmt>>},
+    Break{keyword: Token},
+    Continue{keyword: Token},
+    //path is the STRING token of the imported file, name is the variable the module is bound to
+    Import{keyword: Token, path: Token, name: Token},
+    Throw{keyword: Token, value: Rc<Expr>},
+    //catch is the name the error is bound to and the handler. At least one of catch and finally is present
+    Try{keyword: Token, body: Vec<Rc<Stmt>>, catch: Option<(Token, Vec<Rc<Stmt>>)>, finally: Option<Vec<Rc<Stmt>>>},
 }
 //This trait should be implemented if the visitor seeks to use the values that Stmt stores
 //Using this trait avoids needing to match and unwrap all of the enum values
@@ -17,11 +28,17 @@ pub trait Visitor<T> {
     fn visit_expression_stmt(&self, expression: Rc<Expr>) -> T;
     fn visit_print_stmt(&self, expression: Rc<Expr>) -> T;
     fn visit_var_stmt(&self, name: Token, initializer: Rc<Expr>) -> T;
-    fn visit_block_stmt(&self, statements: &Vec<Rc<Stmt>>) -> T;
+    fn visit_block_stmt(&self, brace: Token, statements: &Vec<Rc<Stmt>>) -> T;
     fn visit_if_stmt(&self, condition: Rc<Expr>, then_branch: Rc<Stmt>, else_branch: &Option<Rc<Stmt>>) -> T;
-    fn visit_while_stmt(&self, condition: Rc<Expr>, body: Rc<Stmt>) -> T;
+    fn visit_while_stmt(&self, condition: Rc<Expr>, body: Rc<Stmt>, increment: &Option<Rc<Expr>>) -> T;
     fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Rc<Stmt>>) -> T;
-    fn visit_return_stmt(&self, keyword: Token, value: Rc<Expr>) -> T;
+    fn visit_return_stmt(&self, keyword: Token, value: Option<Rc<Expr>>) -> T;
+    fn visit_class_stmt(&self, name: Token, superclass: &Option<Rc<Expr>>, methods: &Vec<Rc<Stmt>>) -> T;
+    fn visit_break_stmt(&self, keyword: Token) -> T;
+    fn visit_continue_stmt(&self, keyword: Token) -> T;
+    fn visit_import_stmt(&self, keyword: Token, path: Token, name: Token) -> T;
+    fn visit_throw_stmt(&self, keyword: Token, value: Rc<Expr>) -> T;
+    fn visit_try_stmt(&self, keyword: Token, body: &Vec<Rc<Stmt>>, catch: &Option<(Token, Vec<Rc<Stmt>>)>, finally: &Option<Vec<Rc<Stmt>>>) -> T;
 }
 //This trait should be implemented if the visitor seeks to use the a reference to the Stmt itself
 //This is used in the resolver as it was designed in the textbook to pass around Stmts in its functions
@@ -34,6 +51,12 @@ pub trait VisitorStmt<T> {
     fn visit_while_stmt(&self, statement: Rc<Stmt>) -> T;
     fn visit_function_stmt(&self, statement: Rc<Stmt>) -> T;
     fn visit_return_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_class_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_break_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_continue_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_import_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_throw_stmt(&self, statement: Rc<Stmt>) -> T;
+    fn visit_try_stmt(&self, statement: Rc<Stmt>) -> T;
 }
 
 impl Stmt{
@@ -42,11 +65,17 @@ impl Stmt{
             Self::Expression {expression } => visitor.visit_expression_stmt(expression.clone()),
             Self::Print {expression } => visitor.visit_print_stmt(expression.clone()),
             Self::Var {name, initializer } => visitor.visit_var_stmt(name.clone(), initializer.clone()),
-            Self::Block {statements} => visitor.visit_block_stmt(statements), 
+            Self::Block {brace, statements} => visitor.visit_block_stmt(brace.clone(), statements), 
             Self::If {condition, then_branch, else_branch} => visitor.visit_if_stmt(condition.clone(), then_branch.clone(), else_branch),
-            Self::While {condition, body} => visitor.visit_while_stmt(condition.clone(), body.clone()),
+            Self::While {condition, body, increment} => visitor.visit_while_stmt(condition.clone(), body.clone(), increment),
             Self::Function {name, params, body} => visitor.visit_function_stmt(name.clone(), params.clone(), body.clone()),
-            Self::Return {keyword, value} => visitor.visit_return_stmt(keyword.clone(), value.clone())
+            Self::Return {keyword, value} => visitor.visit_return_stmt(keyword.clone(), value.clone()),
+            Self::Class {name, superclass, methods} => visitor.visit_class_stmt(name.clone(), superclass, methods),
+            Self::Break {keyword} => visitor.visit_break_stmt(keyword.clone()),
+            Self::Continue {keyword} => visitor.visit_continue_stmt(keyword.clone()),
+            Self::Import {keyword, path, name} => visitor.visit_import_stmt(keyword.clone(), path.clone(), name.clone()),
+            Self::Throw {keyword, value} => visitor.visit_throw_stmt(keyword.clone(), value.clone()),
+            Self::Try {keyword, body, catch, finally} => visitor.visit_try_stmt(keyword.clone(), body, catch, finally),
         }
     }
 
@@ -56,11 +85,17 @@ impl Stmt{
             Self::Expression {expression:_ } => visitor.visit_expression_stmt(stmt),
             Self::Print {expression:_ } =>visitor.visit_print_stmt(stmt),
             Self::Var {name:_, initializer:_ } => visitor.visit_var_stmt(stmt),
-            Self::Block {statements:_} => visitor.visit_block_stmt(stmt), 
+            Self::Block {brace:_, statements:_} => visitor.visit_block_stmt(stmt), 
             Self::If {condition:_, then_branch:_, else_branch:_} => visitor.visit_if_stmt(stmt),
-            Self::While {condition:_, body:_} => visitor.visit_while_stmt(stmt),
+            Self::While {condition:_, body:_, increment:_} => visitor.visit_while_stmt(stmt),
             Self::Function {name:_, params:_, body:_} => visitor.visit_function_stmt(stmt),
-            Self::Return {keyword:_, value:_} => visitor.visit_return_stmt(stmt)
+            Self::Return {keyword:_, value:_} => visitor.visit_return_stmt(stmt),
+            Self::Class {name:_, superclass:_, methods:_} => visitor.visit_class_stmt(stmt),
+            Self::Break {keyword:_} => visitor.visit_break_stmt(stmt),
+            Self::Continue {keyword:_} => visitor.visit_continue_stmt(stmt),
+            Self::Import {keyword:_, path:_, name:_} => visitor.visit_import_stmt(stmt),
+            Self::Throw {keyword:_, value:_} => visitor.visit_throw_stmt(stmt),
+            Self::Try {keyword:_, body:_, catch:_, finally:_} => visitor.visit_try_stmt(stmt),
         }
     }
 }
@@ -0,0 +1,265 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+
+//This file defines the native functions that operate on strings.
+//Strings are indexed by character (Unicode scalar value) rather than by byte, so no native can split a character.
+//len() is shared with lists and maps and lives in list_natives.rs
+
+pub fn define_string_natives(globals: &mut Environment) {
+    define_native(globals, "substring", 3, substring);
+    define_native(globals, "index_of", 2, index_of);
+    define_native(globals, "split", 2, split);
+    define_native(globals, "join", 2, join);
+    define_native(globals, "trim", 1, trim);
+    define_native(globals, "upper", 1, upper);
+    define_native(globals, "lower", 1, lower);
+    define_native(globals, "replace", 3, replace);
+    define_native(globals, "starts_with", 2, starts_with);
+    define_native(globals, "ends_with", 2, ends_with);
+    define_native(globals, "char_at", 2, char_at);
+    define_native(globals, "ord", 1, ord);
+    define_native(globals, "chr", 1, chr);
+}
+
+fn expect_string<'a>(value: &'a LoxValue, function: &str) -> Result<&'a str, RuntimeError> {
+    match value {
+        LoxValue::LoxString(string) => Ok(string),
+        _ => Err(native_error([function, "() expects a string."].concat())),
+    }
+}
+
+//For natives that take two strings, e.g. starts_with(s, prefix)
+fn expect_strings<'a>(
+    arguments: &'a [LoxValue],
+    function: &str,
+) -> Result<(&'a str, &'a str), RuntimeError> {
+    match (&arguments[0], &arguments[1]) {
+        (LoxValue::LoxString(first), LoxValue::LoxString(second)) => Ok((first, second)),
+        _ => Err(native_error([function, "() expects two strings."].concat())),
+    }
+}
+
+//Converts a Lox value into a character index no larger than "upper", like LoxList::checked_index
+fn checked_char_index(index: &LoxValue, upper: usize) -> Result<usize, RuntimeError> {
+    match index {
+        LoxValue::Number(number) if number.fract() == 0.0 => {
+            if *number < 0.0 || *number > upper as f64 {
+                Err(native_error("String index out of range.".to_string()))
+            } else {
+                Ok(*number as usize)
+            }
+        }
+        _ => Err(native_error("String index must be an integer.".to_string())),
+    }
+}
+
+//Every native that builds a string counts it against the sandbox's string limit.
+//The size is worked out first, so a string over the limit is never built
+fn reserve(bytes: usize, interpreter: &Interpreter) -> Result<(), RuntimeError> {
+    interpreter.allocate_string(bytes).map_err(native_error)
+}
+
+//Returns the characters in [start, end)
+fn substring(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "substring")?;
+    let length = string.chars().count();
+    let start = checked_char_index(&arguments[1], length)?;
+    let end = checked_char_index(&arguments[2], length)?;
+    if start > end {
+        return Err(native_error(
+            "Substring start must not be greater than its end.".to_string(),
+        ));
+    }
+    let byte_offset = |index: usize| {
+        string
+            .char_indices()
+            .nth(index)
+            .map_or(string.len(), |(offset, _)| offset)
+    };
+    let (from, to) = (byte_offset(start), byte_offset(end));
+    reserve(to - from, interpreter)?;
+    Ok(LoxValue::LoxString(string[from..to].to_string()))
+}
+
+//Returns the character index of the first occurrence, or -1
+fn index_of(
+    arguments: Vec<LoxValue>,
+    _interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let (string, target) = expect_strings(&arguments, "index_of")?;
+    let index = match string.find(target) {
+        Some(byte_index) => string[..byte_index].chars().count() as f64,
+        None => -1.0,
+    };
+    Ok(LoxValue::Number(index))
+}
+
+//An empty separator splits the string into its characters
+fn split(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let (string, separator) = expect_strings(&arguments, "split")?;
+    let parts: Vec<&str> = if separator.is_empty() {
+        string
+            .char_indices()
+            .map(|(offset, c)| &string[offset..offset + c.len_utf8()])
+            .collect()
+    } else {
+        string.split(separator).collect()
+    };
+    interpreter
+        .check_store(parts.len(), &[])
+        .map_err(native_error)?;
+    reserve(parts.iter().map(|part| part.len()).sum(), interpreter)?;
+    let elements = parts
+        .into_iter()
+        .map(|part| LoxValue::LoxString(part.to_string()))
+        .collect();
+    Ok(LoxValue::List(interpreter.new_list(elements)))
+}
+
+fn join(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let message = "join() expects a list of strings and a separator string.";
+    let (list, separator) = match (&arguments[0], &arguments[1]) {
+        (LoxValue::List(list), LoxValue::LoxString(separator)) => (list, separator),
+        _ => return Err(native_error(message.to_string())),
+    };
+    let elements = list.elements.borrow();
+    let mut bytes = separator
+        .len()
+        .saturating_mul(elements.len().saturating_sub(1));
+    for element in elements.iter() {
+        match element {
+            LoxValue::LoxString(string) => bytes = bytes.saturating_add(string.len()),
+            _ => return Err(native_error(message.to_string())),
+        }
+    }
+    reserve(bytes, interpreter)?;
+
+    let mut result = String::with_capacity(bytes);
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            result.push_str(separator);
+        }
+        if let LoxValue::LoxString(string) = element {
+            result.push_str(string);
+        }
+    }
+    Ok(LoxValue::LoxString(result))
+}
+
+fn trim(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "trim")?;
+    let trimmed = string.trim();
+    reserve(trimmed.len(), interpreter)?;
+    Ok(LoxValue::LoxString(trimmed.to_string()))
+}
+
+fn upper(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "upper")?;
+    reserve(
+        string
+            .chars()
+            .flat_map(char::to_uppercase)
+            .map(char::len_utf8)
+            .sum(),
+        interpreter,
+    )?;
+    Ok(LoxValue::LoxString(string.to_uppercase()))
+}
+
+fn lower(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "lower")?;
+    //str::to_lowercase also turns a final 'Σ' into 'ς' rather than 'σ', which has the same length
+    reserve(
+        string
+            .chars()
+            .flat_map(char::to_lowercase)
+            .map(char::len_utf8)
+            .sum(),
+        interpreter,
+    )?;
+    Ok(LoxValue::LoxString(string.to_lowercase()))
+}
+
+//Replaces every occurrence of "from" with "to"
+fn replace(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let (string, from) = expect_strings(&arguments, "replace")?;
+    let to = match &arguments[2] {
+        LoxValue::LoxString(to) => to,
+        _ => return Err(native_error("replace() expects three strings.".to_string())),
+    };
+    if from.is_empty() {
+        return Err(native_error(
+            "replace() expects a non-empty string to replace.".to_string(),
+        ));
+    }
+    let count = string.matches(from).count();
+    let bytes = (string.len() - count * from.len()).saturating_add(count.saturating_mul(to.len()));
+    reserve(bytes, interpreter)?;
+    Ok(LoxValue::LoxString(string.replace(from, to)))
+}
+
+fn starts_with(
+    arguments: Vec<LoxValue>,
+    _interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let (string, prefix) = expect_strings(&arguments, "starts_with")?;
+    Ok(LoxValue::Boolean(string.starts_with(prefix)))
+}
+
+fn ends_with(
+    arguments: Vec<LoxValue>,
+    _interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let (string, suffix) = expect_strings(&arguments, "ends_with")?;
+    Ok(LoxValue::Boolean(string.ends_with(suffix)))
+}
+
+fn char_at(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "char_at")?;
+    let length = string.chars().count();
+    if length == 0 {
+        return Err(native_error("String index out of range.".to_string()));
+    }
+    let index = checked_char_index(&arguments[1], length - 1)?;
+    let character = string.chars().nth(index).unwrap();
+    reserve(character.len_utf8(), interpreter)?;
+    Ok(LoxValue::LoxString(character.to_string()))
+}
+
+//Returns the code point of a one character string
+fn ord(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let string = expect_string(&arguments[0], "ord")?;
+    let mut characters = string.chars();
+    match (characters.next(), characters.next()) {
+        (Some(character), None) => Ok(LoxValue::Number(character as u32 as f64)),
+        _ => Err(native_error(
+            "ord() expects a string of exactly one character.".to_string(),
+        )),
+    }
+}
+
+//Returns the one character string for a code point
+fn chr(arguments: Vec<LoxValue>, interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    let character = match &arguments[0] {
+        //Numbers beyond u32::MAX saturate to an invalid code point
+        LoxValue::Number(number) if number.fract() == 0.0 && *number >= 0.0 => {
+            char::from_u32(*number as u32)
+        }
+        LoxValue::Number(_) => None,
+        _ => return Err(native_error("chr() expects a number.".to_string())),
+    };
+    match character {
+        Some(character) => {
+            reserve(character.len_utf8(), interpreter)?;
+            Ok(LoxValue::LoxString(character.to_string()))
+        }
+        None => Err(native_error(
+            "chr() expects a valid Unicode code point.".to_string(),
+        )),
+    }
+}
@@ -9,7 +9,10 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     PERCENTAGE,
+    COLON,
     COMMA,
     DOT,
     MINUS,
@@ -35,19 +38,26 @@ pub enum TokenType {
 
     // Keywords.
     AND,
+    BREAK,
+    CATCH,
     CLASS,
+    CONTINUE,
     ELSE,
     FALSE,
+    FINALLY,
     FUN,
     FOR,
     IF,
+    IMPORT,
     NIL,
     OR,
     PRINT,
     RETURN,
     SUPER,
     THIS,
+    THROW,
     TRUE,
+    TRY,
     VAR,
     WHILE,
 
@@ -60,15 +70,38 @@ pub struct Token {
     pub lexeme: String,
     pub literal: LoxValue,
     pub line: u32,
+    //1-based column of the first character of the lexeme, 0 for tokens not taken from the source
+    pub column: u32,
+    //Byte offset of the first character of the lexeme in the source
+    pub offset: usize,
 }
 
 impl Token {
-    pub fn new(kind: TokenType, lexeme: String, literal: LoxValue, line: u32) -> Self {
+    pub fn new(
+        kind: TokenType,
+        lexeme: String,
+        literal: LoxValue,
+        line: u32,
+        column: u32,
+        offset: usize,
+    ) -> Self {
         Token {
             kind,
             lexeme,
             literal,
             line,
+            column,
+            offset,
         }
     }
+
+    //For tokens created by the implementation rather than scanned from the source
+    pub fn synthetic(kind: TokenType, lexeme: &str, line: u32) -> Self {
+        Token::new(kind, lexeme.to_string(), LoxValue::Nil, line, 0, 0)
+    }
+
+    //Number of columns the token covers, at least one so that the end of the file can be pointed at
+    pub fn width(&self) -> u32 {
+        self.lexeme.chars().count().max(1) as u32
+    }
 }
@@ -0,0 +1,84 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::lox_type::LoxValue;
+use crate::native_function::{define_native, native_error};
+use std::fmt::{self, Write};
+
+//This file defines the natives that inspect and convert the types of values
+
+pub fn define_type_natives(globals: &mut Environment) {
+    define_native(globals, "type_of", 1, type_of);
+    define_native(globals, "to_string", 1, to_string);
+    define_native(globals, "parse_number", 1, parse_number);
+    define_native(globals, "is_integer", 1, is_integer);
+}
+
+fn type_of(arguments: Vec<LoxValue>, _interpreter: &Interpreter) -> Result<LoxValue, RuntimeError> {
+    Ok(LoxValue::LoxString(arguments[0].type_name().to_string()))
+}
+
+//Collects formatted text until it passes "max_bytes", then refuses the rest
+struct BoundedWriter {
+    text: String,
+    max_bytes: usize,
+}
+
+impl fmt::Write for BoundedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.text.len().saturating_add(s.len()) > self.max_bytes {
+            return Err(fmt::Error);
+        }
+        self.text.push_str(s);
+        Ok(())
+    }
+}
+
+//Returns the value exactly as print would show it.
+//Large lists and maps can print as far more text than they hold, so formatting stops at the sandbox's string limit
+fn to_string(
+    arguments: Vec<LoxValue>,
+    interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let max_bytes = interpreter.string_budget();
+    let mut writer = BoundedWriter {
+        text: String::new(),
+        max_bytes,
+    };
+    let bytes = match write!(writer, "{}", arguments[0]) {
+        Ok(()) => writer.text.len(),
+        Err(_) => max_bytes.saturating_add(1),
+    };
+    interpreter.allocate_string(bytes).map_err(native_error)?;
+    Ok(LoxValue::LoxString(writer.text))
+}
+
+//Accepts decimal numbers with an optional sign and exponent, ignoring surrounding whitespace. Returns nil otherwise
+fn parse_number(
+    arguments: Vec<LoxValue>,
+    _interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let string = match &arguments[0] {
+        LoxValue::LoxString(string) => string.trim(),
+        _ => return Err(native_error("parse_number() expects a string.".to_string())),
+    };
+    //Rust also parses "inf" and "NaN", which are not Lox numbers
+    let numeric = string
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+    match string.parse::<f64>() {
+        Ok(number) if numeric && number.is_finite() => Ok(LoxValue::Number(number)),
+        _ => Ok(LoxValue::Nil),
+    }
+}
+
+//False for anything that is not a number, so it can be used to validate data
+fn is_integer(
+    arguments: Vec<LoxValue>,
+    _interpreter: &Interpreter,
+) -> Result<LoxValue, RuntimeError> {
+    let integer = match &arguments[0] {
+        LoxValue::Number(number) => number.is_finite() && number.fract() == 0.0,
+        _ => false,
+    };
+    Ok(LoxValue::Boolean(integer))
+}
@@ -0,0 +1,546 @@
+use crate::chunk::OpCode;
+use crate::interpreter::RuntimeError;
+use crate::vm_value::{BoundMethod, Class, Closure, FunctionProto, Instance, Upvalue, Value, VmNative};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//Maximum depth of Lox calls before the VM reports a stack overflow
+const FRAMES_MAX: usize = 4096;
+
+//A single function invocation. slot_base is the index of the callee (or receiver) on the value stack
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    slot_base: usize,
+}
+
+//This struct executes the bytecode produced by the Compiler.
+//Globals persist across calls to interpret so that the REPL keeps its state.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    //Upvalues that still point into the stack, sorted by stack slot
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    init_string: Rc<str>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clock(_arguments: &[Value]) -> Value {
+    let since_the_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    Value::Number(since_the_epoch.as_millis() as f64)
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Vm {
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: HashMap::new(),
+            open_upvalues: Vec::new(),
+            init_string: Rc::from("init"),
+        };
+        vm.define_native("clock", 0, clock);
+        vm
+    }
+
+    fn define_native(&mut self, name: &str, arity: u32, function: fn(&[Value]) -> Value) {
+        self.globals
+            .insert(Rc::from(name), Value::Native(Rc::new(VmNative { arity, function })));
+    }
+
+    //Returns the value the script returns, which is the value of a trailing expression statement or nil
+    pub fn interpret(&mut self, script: Rc<FunctionProto>, output_stream: &mut dyn io::Write) -> Result<Value, RuntimeError> {
+        let closure = Rc::new(Closure {
+            function: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(closure.clone()));
+        self.call(closure, 0)?;
+
+        let result = self.run(output_stream);
+        output_stream.flush().expect("Could not flush to output buffer.");
+        if result.is_err() {
+            //Leave the VM usable (e.g. by the REPL) after a runtime error
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frames.last_mut().unwrap();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frames.last_mut().unwrap();
+        let value = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.frame().closure.function.chunk.constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Value::LoxString(string) => string,
+            _ => unreachable!("Compiler emitted a non-string name constant"),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("VM stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    //Builds an error pointing at the line of the instruction currently executing, with the stack trace of every frame
+    fn error(&self, message: String) -> RuntimeError {
+        let line_of = |frame: &CallFrame| frame.closure.function.chunk.lines[frame.ip.saturating_sub(1)];
+        let line = self.frames.last().map(line_of).unwrap_or(0);
+        let mut error = RuntimeError::new(message, line);
+        error.trace = self
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            //The first frame is always the script
+            .map(|(i, frame)| match i {
+                0 => format!("[line {}] in script", line_of(frame)),
+                _ => format!("[line {}] in {}()", line_of(frame), frame.closure.function.name),
+            })
+            .collect();
+        error
+    }
+
+    fn number_operands(&mut self) -> Result<(f64, f64), RuntimeError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(left), Value::Number(right)) => {
+                let operands = (*left, *right);
+                self.stack.truncate(self.stack.len() - 2);
+                Ok(operands)
+            }
+            _ => Err(self.error("Operand must be a number.".to_string())),
+        }
+    }
+
+    fn run(&mut self, output_stream: &mut dyn io::Write) -> Result<Value, RuntimeError> {
+        loop {
+            match OpCode::from(self.read_byte()) {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slot_base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slot_base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(self.error(["Undefined variable '", &name, "'."].concat())),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.error(["Undefined variable '", &name, "'."].concat())),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = match &*self.frame().closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let value = self.peek(0).clone();
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(0) {
+                        Value::Instance(instance) => instance.clone(),
+                        _ => return Err(self.error("Only instances have properties.".to_string())),
+                    };
+
+                    let field = instance.fields.borrow().get(&name).cloned();
+                    match field {
+                        Some(value) => {
+                            self.pop();
+                            self.stack.push(value);
+                        }
+                        None => self.bind_method(&instance.class, &name)?,
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(1) {
+                        Value::Instance(instance) => instance.clone(),
+                        _ => return Err(self.error("Only instances have fields.".to_string())),
+                    };
+
+                    let value = self.pop();
+                    instance.fields.borrow_mut().insert(name, value.clone());
+                    self.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let superclass = match self.pop() {
+                        Value::Class(class) => class,
+                        _ => unreachable!("'super' is always bound to a class"),
+                    };
+                    self.bind_method(&superclass, &name)?;
+                }
+                OpCode::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.stack.push(Value::Boolean(left == right));
+                }
+                OpCode::Greater => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Boolean(left > right));
+                }
+                OpCode::GreaterEqual => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Boolean(left >= right));
+                }
+                OpCode::Less => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Boolean(left < right));
+                }
+                OpCode::LessEqual => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Boolean(left <= right));
+                }
+                OpCode::Add => {
+                    let result = match (self.peek(1), self.peek(0)) {
+                        (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
+                        (Value::LoxString(left), Value::LoxString(right)) => {
+                            Value::LoxString(Rc::from([&**left, &**right].concat()))
+                        }
+                        (Value::Number(_), _) | (Value::LoxString(_), _) => {
+                            return Err(self.error("Operand types do not match".to_string()))
+                        }
+                        _ => {
+                            return Err(self.error(
+                                "Invalid operands. Operands must be numbers or Strings".to_string(),
+                            ))
+                        }
+                    };
+                    self.stack.truncate(self.stack.len() - 2);
+                    self.stack.push(result);
+                }
+                OpCode::Subtract => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Number(left - right));
+                }
+                OpCode::Multiply => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Number(left * right));
+                }
+                OpCode::Divide => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Number(left / right));
+                }
+                OpCode::Modulo => {
+                    let (left, right) = self.number_operands()?;
+                    self.stack.push(Value::Number(left % right));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(value) => self.stack.push(Value::Number(-value)),
+                    _ => return Err(self.error("Operand must be a number.".to_string())),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(output_stream, "{}", value).expect("Could not write to provided output buffer.");
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frames.last_mut().unwrap().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        self.frames.last_mut().unwrap().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frames.last_mut().unwrap().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(self.peek(arg_count).clone(), arg_count)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_string();
+                    let arg_count = self.read_byte() as usize;
+                    self.invoke(&name, arg_count)?;
+                }
+                OpCode::SuperInvoke => {
+                    let name = self.read_string();
+                    let arg_count = self.read_byte() as usize;
+                    let superclass = match self.pop() {
+                        Value::Class(class) => class,
+                        _ => unreachable!("'super' is always bound to a class"),
+                    };
+                    self.invoke_from_class(&superclass, &name, arg_count)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Value::Function(function) => function,
+                        _ => unreachable!("Compiler emitted a non-function closure constant"),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        if is_local {
+                            let slot = self.frame().slot_base + index;
+                            upvalues.push(self.capture_upvalue(slot));
+                        } else {
+                            upvalues.push(self.frame().closure.upvalues[index].clone());
+                        }
+                    }
+
+                    self.stack.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slot_base);
+                    self.stack.truncate(frame.slot_base);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    self.stack.push(Value::Class(Rc::new(Class {
+                        name: name.to_string(),
+                        methods: RefCell::new(HashMap::new()),
+                    })));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Class(class) => class.clone(),
+                        _ => return Err(self.error("Superclass must be a class.".to_string())),
+                    };
+                    if let Value::Class(subclass) = self.peek(0) {
+                        //Copy-down inheritance: methods defined later in the subclass override these
+                        subclass
+                            .methods
+                            .borrow_mut()
+                            .extend(superclass.methods.borrow().iter().map(|(k, v)| (k.clone(), v.clone())));
+                    }
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = match self.pop() {
+                        Value::Closure(closure) => closure,
+                        _ => unreachable!("Compiler emitted a method that is not a closure"),
+                    };
+                    if let Value::Class(class) = self.peek(0) {
+                        class.methods.borrow_mut().insert(name, method);
+                    }
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), RuntimeError> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, arg_count),
+            Value::Native(native) => {
+                self.check_arity(native.arity, arg_count)?;
+                let arguments_start = self.stack.len() - arg_count;
+                let result = (native.function)(&self.stack[arguments_start..]);
+                self.stack.truncate(arguments_start - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            Value::Class(class) => {
+                //The instance replaces the class in the callee slot and becomes "this" for init
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = Value::Instance(Rc::new(Instance {
+                    class: class.clone(),
+                    fields: RefCell::new(HashMap::new()),
+                }));
+
+                let initializer = class.methods.borrow().get(&self.init_string).cloned();
+                match initializer {
+                    Some(initializer) => self.call(initializer, arg_count),
+                    None => self.check_arity(0, arg_count),
+                }
+            }
+            Value::BoundMethod(bound) => {
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = bound.receiver.clone();
+                self.call(bound.method.clone(), arg_count)
+            }
+            _ => Err(self.error("Can only call functions and classes.".to_string())),
+        }
+    }
+
+    fn check_arity(&self, arity: u32, arg_count: usize) -> Result<(), RuntimeError> {
+        if arity as usize != arg_count {
+            return Err(self.error(format!(
+                "Expected {} arguments but got {}.",
+                arity, arg_count
+            )));
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, arg_count: usize) -> Result<(), RuntimeError> {
+        self.check_arity(closure.function.arity, arg_count)?;
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("Stack overflow.".to_string()));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slot_base: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    fn invoke(&mut self, name: &Rc<str>, arg_count: usize) -> Result<(), RuntimeError> {
+        let instance = match self.peek(arg_count) {
+            Value::Instance(instance) => instance.clone(),
+            _ => return Err(self.error("Only instances have properties.".to_string())),
+        };
+
+        //A field holding a function shadows a method of the same name
+        let field = instance.fields.borrow().get(name).cloned();
+        match field {
+            Some(value) => {
+                let slot = self.stack.len() - arg_count - 1;
+                self.stack[slot] = value.clone();
+                self.call_value(value, arg_count)
+            }
+            None => self.invoke_from_class(&instance.class, name, arg_count),
+        }
+    }
+
+    fn invoke_from_class(&mut self, class: &Rc<Class>, name: &Rc<str>, arg_count: usize) -> Result<(), RuntimeError> {
+        let method = class.methods.borrow().get(name).cloned();
+        match method {
+            Some(method) => self.call(method, arg_count),
+            None => Err(self.error(["Undefined property '", name, "'."].concat())),
+        }
+    }
+
+    //Replaces the receiver on top of the stack with the named method bound to it
+    fn bind_method(&mut self, class: &Rc<Class>, name: &Rc<str>) -> Result<(), RuntimeError> {
+        let method = class.methods.borrow().get(name).cloned();
+        match method {
+            Some(method) => {
+                let receiver = self.pop();
+                self.stack
+                    .push(Value::BoundMethod(Rc::new(BoundMethod { receiver, method })));
+                Ok(())
+            }
+            None => Err(self.error(["Undefined property '", name, "'."].concat())),
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self.open_upvalues.iter().rposition(|upvalue| match *upvalue.borrow() {
+            Upvalue::Open(open_slot) => open_slot <= slot,
+            Upvalue::Closed(_) => false,
+        });
+
+        if let Some(index) = position {
+            if let Upvalue::Open(open_slot) = *self.open_upvalues[index].borrow() {
+                if open_slot == slot {
+                    return self.open_upvalues[index].clone();
+                }
+            }
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        let insert_at = position.map_or(0, |index| index + 1);
+        self.open_upvalues.insert(insert_at, upvalue.clone());
+        upvalue
+    }
+
+    //Moves every captured variable at or above last_slot off the stack and into its upvalue
+    fn close_upvalues(&mut self, last_slot: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => unreachable!("Closed upvalue in the open list"),
+            };
+            if slot < last_slot {
+                break;
+            }
+            let upvalue = self.open_upvalues.pop().unwrap();
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack[slot].clone());
+        }
+    }
+}
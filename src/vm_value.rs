@@ -0,0 +1,123 @@
+use crate::chunk::Chunk;
+use crate::lox_type::{stringify_double, LoxValue};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+//This enum represents all values that live on the VM's stack.
+//It mirrors LoxValue, but functions are compiled prototypes and closures rather than AST declarations.
+#[derive(Clone)]
+pub enum Value {
+    Boolean(bool),
+    Number(f64),
+    LoxString(Rc<str>),
+    Nil,
+    //Only appears in constant pools. Wrapped in a Closure at runtime
+    Function(Rc<FunctionProto>),
+    Closure(Rc<Closure>),
+    Native(Rc<VmNative>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+//The compiled form of a function declaration
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: u32,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+//An upvalue points at a stack slot while the captured variable is in scope and owns the value once it is closed
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+//A native function callable from the VM
+pub struct VmNative {
+    pub arity: u32,
+    pub function: fn(&[Value]) -> Value,
+}
+
+//Methods are added after the class is created, one Method instruction at a time
+pub struct Class {
+    pub name: String,
+    pub methods: RefCell<HashMap<Rc<str>, Rc<Closure>>>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Rc<str>, Value>>,
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        //Nil is false, Boolean is value, all others are true
+        match self {
+            Self::Boolean(val) => *val,
+            Self::Nil => false,
+            _ => true,
+        }
+    }
+
+    //Converts a VM value for the embedding API. Objects have no LoxValue equivalent so they are returned in printed form
+    pub fn to_lox_value(&self) -> LoxValue {
+        match self {
+            Self::Boolean(val) => LoxValue::Boolean(*val),
+            Self::Number(val) => LoxValue::Number(*val),
+            Self::LoxString(val) => LoxValue::LoxString(val.to_string()),
+            Self::Nil => LoxValue::Nil,
+            _ => LoxValue::LoxString(self.to_string()),
+        }
+    }
+}
+
+//Equality follows the tree-walk interpreter: primitives compare by value, objects by identity
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::LoxString(a), Self::LoxString(b)) => a == b,
+            (Self::Nil, Self::Nil) => true,
+            (Self::Function(a), Self::Function(b)) => Rc::ptr_eq(a, b),
+            (Self::Closure(a), Self::Closure(b)) => Rc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Rc::ptr_eq(a, b),
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            //Every access to a method creates a new bound method, so compare the underlying method
+            (Self::BoundMethod(a), Self::BoundMethod(b)) => Rc::ptr_eq(&a.method, &b.method),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Boolean(val) => write!(f, "{:?}", val),
+            Self::Number(val) => write!(f, "{}", stringify_double(val)),
+            Self::LoxString(val) => write!(f, "{}", val),
+            Self::Nil => write!(f, "nil"),
+            Self::Function(function) => write!(f, "<fn {}>", function.name),
+            Self::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+            Self::Native(_) => write!(f, "<native fn>"),
+            Self::Class(class) => write!(f, "{}", class.name),
+            Self::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Self::BoundMethod(bound) => write!(f, "<fn {}>", bound.method.function.name),
+        }
+    }
+}
@@ -1,20 +1,27 @@
-use rusty_lox::interpreter::Interpreter;
-use rusty_lox::Lox;
+use rusty_lox::vm::Vm;
+use rusty_lox::interpreter::RuntimeError;
+use rusty_lox::lox_callable::Arity;
+use rusty_lox::lox_error::LoxError;
+use rusty_lox::lox_io::MemoryIo;
+use rusty_lox::lox_type::LoxValue;
+use rusty_lox::sandbox::SandboxConfig;
+use rusty_lox::{with_stack_size, Backend, Lox, WarningMode};
+use std::cell::Cell;
 use std::fs;
 use std::io::BufWriter;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::Duration;
 
-fn run_test_file(test_name: &str) -> String {
+fn run_test_file(test_name: &str, backend: Backend) -> String {
     let mut buf = BufWriter::new(Vec::new());
-    let mut lox = Lox {
-        had_error: false,
-        had_runtime_error: false,
-        interpreter: Rc::new(Interpreter::new()),
-        output_buffer: &mut buf,
-    };
+    let mut lox = Lox::new(&mut buf);
+    lox.backend = backend;
 
     let absolute_filename = ["tests/resources/", test_name, "/input.lox"].concat();
-    lox.run_file(&absolute_filename);
+    lox.run_file(&absolute_filename)
+        .expect("Test script reported an error");
+    drop(lox);
 
     let bytes = buf.into_inner().expect("Error reading from test buffer");
     String::from_utf8(bytes).expect("Error reading from test buffer")
@@ -29,8 +36,19 @@ fn read_expected_output(test_name: &str) -> String {
     .expect("Error reading from expected output file")
 }
 
+//Every test is run on both backends, which must produce identical output
 fn run_and_assert(test_name: &str) {
-    assert_eq!(run_test_file(test_name), read_expected_output(test_name))
+    let expected = read_expected_output(test_name);
+    assert_eq!(run_test_file(test_name, Backend::TreeWalk), expected);
+    assert_eq!(run_test_file(test_name, Backend::Bytecode(Vm::new())), expected);
+}
+
+//For language features that only the tree-walk interpreter supports
+fn run_and_assert_tree_walk(test_name: &str) {
+    assert_eq!(
+        run_test_file(test_name, Backend::TreeWalk),
+        read_expected_output(test_name)
+    );
 }
 
 #[test]
@@ -89,3 +107,923 @@ fn test_stack() {
 fn test_pass_by_value() {
     run_and_assert("pass_by_value");
 }
+#[test]
+fn test_classes() {
+    run_and_assert("classes");
+}
+#[test]
+fn test_inheritance() {
+    run_and_assert("inheritance");
+}
+#[test]
+fn test_lists() {
+    run_and_assert_tree_walk("lists");
+}
+#[test]
+fn test_maps() {
+    run_and_assert_tree_walk("maps");
+}
+
+#[test]
+fn test_returns() {
+    run_and_assert("returns");
+}
+#[test]
+fn test_break_continue() {
+    run_and_assert("break_continue");
+}
+#[test]
+fn test_lambdas() {
+    run_and_assert("lambdas");
+}
+#[test]
+fn test_math_natives() {
+    run_and_assert_tree_walk("math_natives");
+}
+#[test]
+fn test_string_natives() {
+    run_and_assert_tree_walk("string_natives");
+}
+#[test]
+fn test_type_natives() {
+    run_and_assert_tree_walk("type_natives");
+}
+#[test]
+fn test_modules() {
+    run_and_assert_tree_walk("modules");
+}
+#[test]
+fn test_module_errors() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+
+    match lox.run_file("tests/resources/module_cycle/input.lox") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Import cycle detected: a.lox -> b.lox -> a.lox.");
+            assert_eq!(diagnostic.line, 1);
+            assert_eq!(
+                diagnostic.notes,
+                vec!["in module 'a.lox' at line 1", "in module 'b.lox' at line 2"]
+            );
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+
+    //Without a script, paths are relative to the working directory
+    let source = "import \"tests/resources/modules/shared.lox\" as shared;\nshared.counter = 1;";
+    match lox.eval_source(source) {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Can't assign to a module member.")
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+    match lox.eval_source("import \"missing.lox\" as missing;") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert!(diagnostic.message.starts_with("Could not open module 'missing.lox'"))
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+
+    //'as' is only a keyword in imports, it can still name variables, even the module
+    let source = "var as = 2;\nimport \"tests/resources/modules/shared.lox\" as as;\nprint as.counter;";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    match lox.eval_source("import \"missing.lox\" like missing;") {
+        Err(LoxError::Parse(diagnostics)) => {
+            assert_eq!(diagnostics[0].message, "Expect 'as' after module path.");
+            assert_eq!(diagnostics[0].location, "at 'like'");
+        }
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+    drop(lox);
+    assert_eq!(String::from_utf8(buf).unwrap(), "loading shared\n0\n");
+}
+#[test]
+fn test_exceptions() {
+    run_and_assert_tree_walk("exceptions");
+}
+#[test]
+fn test_uncaught_throw() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    match lox.eval_source("var a = 1;\nthrow \"bad \" + \"input\";") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "bad input");
+            assert_eq!((diagnostic.line, diagnostic.location.as_str()), (2, "at 'throw'"));
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+
+    match lox.eval_source("try { print 1; }") {
+        Err(LoxError::Parse(diagnostics)) => {
+            assert_eq!(diagnostics[0].message, "Expect 'catch' or 'finally' after try block.")
+        }
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+}
+#[test]
+fn test_stack_overflow() {
+    let source = "fun forever(n) {\n  return forever(n + 1);\n}\nforever(0);";
+    //The defaults are safe on the 2 MiB stack of a test thread
+    for backend in [Backend::TreeWalk, Backend::Bytecode(Vm::new())] {
+        let mut buf = Vec::new();
+        let mut lox = Lox::new(&mut buf);
+        lox.backend = backend;
+        match lox.eval_source(source) {
+            Err(LoxError::Runtime(diagnostic)) => {
+                assert_eq!(diagnostic.message, "Stack overflow.");
+                assert_eq!(diagnostic.line, 2);
+
+                //Only the innermost and outermost frames are rendered
+                let rendered = diagnostic.render(source);
+                let frames: Vec<&str> = rendered.lines().skip_while(|line| !line.starts_with('[')).collect();
+                assert_eq!(frames.len(), 21);
+                assert_eq!(frames[10], format!("... {} more frames", diagnostic.trace.len() - 20));
+                assert_eq!(frames[19], "[line 2] in forever()");
+                assert_eq!(frames[20], "[line 4] in script");
+            }
+            other => panic!("Expected stack overflow, got {:?}", other),
+        }
+    }
+
+    //Calls nested in expressions and blocks use more native stack each, which is measured too
+    let (open, close) = ("(".repeat(40), ")".repeat(40));
+    let sources = [
+        format!("fun f(n) {{ return 1 + {}f(n + 1){}; }}\nf(0);", open, close),
+        "fun f(n) { { { { { { { { { return f(n + 1); } } } } } } } } }\nf(0);".to_string(),
+        "class A { init(n) { A(n + 1); } }\nA(0);".to_string(),
+    ];
+    for source in &sources {
+        let mut buf = Vec::new();
+        let mut lox = Lox::new(&mut buf);
+        match lox.eval_source(source) {
+            Err(LoxError::Runtime(diagnostic)) => assert_eq!(diagnostic.message, "Stack overflow."),
+            other => panic!("Expected stack overflow, got {:?}", other),
+        }
+    }
+
+    //Deep recursion works with a higher limit and a larger stack
+    let source = "fun count(n) {\n  if (n == 0) return 0;\n  return 1 + count(n - 1);\n}\ncount(2000);";
+    let stack_size = 64 * 1024 * 1024;
+    with_stack_size(stack_size, || {
+        let mut buf = Vec::new();
+        let mut lox = Lox::new(&mut buf);
+        lox.interpreter.set_native_stack_size(stack_size);
+        lox.interpreter.set_max_call_depth(5000);
+        assert_eq!(lox.eval_source(source), Ok(LoxValue::Number(2000.0)));
+    });
+}
+#[test]
+fn test_execution_limits() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+
+    lox.interpreter.set_step_limit(Some(10_000));
+    match lox.eval_source("var i = 0;\nwhile (true) { i = i + 1; }") {
+        Err(LoxError::LimitExceeded(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Execution limit exceeded: more than 10000 steps.")
+        }
+        other => panic!("Expected execution limit, got {:?}", other),
+    }
+
+    //Limits apply to each run and can't be caught
+    assert_eq!(lox.eval_source("i;"), Ok(LoxValue::Number(3332.0)));
+    let source = "try { while (true) {} } catch (e) { print \"caught\"; }";
+    assert!(matches!(lox.eval_source(source), Err(LoxError::LimitExceeded(_))));
+
+    lox.interpreter.set_step_limit(None);
+    lox.interpreter.set_time_limit(Some(Duration::from_millis(50)));
+    match lox.eval_source("fun spin() { while (true) {} }\nspin();") {
+        Err(error @ LoxError::LimitExceeded(_)) => assert_eq!(error.exit_code(), 70),
+        other => panic!("Expected execution limit, got {:?}", other),
+    }
+    assert_eq!(lox.eval_source("1 + 1;"), Ok(LoxValue::Number(2.0)));
+    drop(lox);
+    assert!(buf.is_empty());
+}
+#[test]
+fn test_sandbox() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    lox.interpreter.set_sandbox(SandboxConfig {
+        max_string_bytes: Some(1000),
+        max_environments: Some(100),
+        max_values: Some(150),
+        max_call_depth: Some(50),
+        deny_io: false,
+    });
+
+    //Sandbox errors can be caught, and the script can continue once the resources are released
+    let source = "
+var s = \"ab\";
+try { while (true) s = s + s; } catch (e) { print e.message; }
+fun deep(n) { return deep(n + 1); }
+try { deep(0); } catch (e) { print e.message; }
+fun wide(n) { var a = n; var b = n; var c = n; return wide(n + 1); }
+try { wide(0); } catch (e) { print e.message; }
+fun nest(n) { { { return nest(n + 1); } } }
+try { nest(0); } catch (e) { print e.message; }
+print \"done\";";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+
+    //Without the sandbox the call depth is the interpreter's own again
+    lox.interpreter.set_sandbox(SandboxConfig::default());
+    let source = "fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }\ncount(60);";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Number(60.0)));
+    drop(lox);
+
+    let expected = "Sandbox limit exceeded: more than 1000 bytes of strings.
+Stack overflow.
+Sandbox limit exceeded: more than 150 live values.
+Sandbox limit exceeded: more than 100 live environments.
+done
+";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+#[test]
+fn test_sandbox_containers() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    lox.interpreter.set_sandbox(SandboxConfig {
+        max_string_bytes: Some(1000),
+        max_values: Some(300),
+        ..SandboxConfig::default()
+    });
+
+    //List elements and map entries count as live values, and are released with their list or map
+    let source = "
+var xs = [];
+try { while (true) push(xs, 1); } catch (e) { print e.message; }
+xs = nil;
+var m = {};
+var i = 0;
+try { while (true) { m[i] = i; i = i + 1; } } catch (e) { print e.message; }
+m = nil;
+var ys = [1, 2, 3];
+print len(ys);";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+
+    //Strings stored in a list count against the string limit
+    let source = format!(
+        "var xs = []; try {{ while (true) push(xs, \"{}\"); }} catch (e) {{ print e.message; }}",
+        "a".repeat(100)
+    );
+    assert_eq!(lox.eval_source(&source), Ok(LoxValue::Nil));
+
+    //Blocks kept alive by closures count as environments
+    lox.interpreter.set_sandbox(SandboxConfig {
+        max_environments: Some(100),
+        ..SandboxConfig::default()
+    });
+    let source = "
+var fs = {};
+var i = 0;
+try { while (true) { { fs[i] = fun () { return i; }; } i = i + 1; } } catch (e) { print e.message; }";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    drop(lox);
+
+    let expected = "Sandbox limit exceeded: more than 300 live values.
+Sandbox limit exceeded: more than 300 live values.
+3
+Sandbox limit exceeded: more than 1000 bytes of strings.
+Sandbox limit exceeded: more than 100 live environments.
+";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+#[test]
+fn test_deep_nesting() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+
+    //Deeply nested code is a parse error rather than a native stack overflow
+    let depth = 100000;
+    let parens = format!("print {}1{};", "(".repeat(depth), ")".repeat(depth));
+    let blocks = format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+    let unary = format!("print {}1;", "-".repeat(depth));
+    for source in [&parens, &blocks, &unary] {
+        match lox.eval_source(source) {
+            Err(LoxError::Parse(diagnostics)) => {
+                assert_eq!(diagnostics.len(), 1);
+                assert_eq!(diagnostics[0].message, "Code is nested too deeply.");
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+    //Code nested less deeply than the limit still runs
+    let source = format!("{}print {}1{};{}", "{".repeat(40), "(".repeat(40), ")".repeat(40), "}".repeat(40));
+    assert_eq!(lox.eval_source(&source), Ok(LoxValue::Nil));
+
+    //Deeply nested values can be printed and freed
+    let source = "
+var l = [];
+for (var i = 0; i < 50000; i = i + 1) l = [l];
+var m = {};
+for (var i = 0; i < 50000; i = i + 1) m = {\"m\": m};
+class Node {}
+var head = nil;
+for (var i = 0; i < 50000; i = i + 1) { var n = Node(); n.next = head; head = n; }
+print len(to_string(l));
+l = nil;
+m = nil;
+head = nil;";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+
+    //Instance fields count against the sandbox's value limit
+    lox.interpreter.set_sandbox(SandboxConfig {
+        max_values: Some(1000),
+        ..SandboxConfig::default()
+    });
+    let source = "
+class Node {}
+var head = nil;
+try { while (true) { var n = Node(); n.next = head; head = n; } } catch (e) { print e.message; }";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    drop(lox);
+
+    let expected = "1\n133\nSandbox limit exceeded: more than 1000 live values.\n";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+#[test]
+fn test_sandbox_string_natives() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let io = Rc::new(MemoryIo::new(&[&"x".repeat(5000)]));
+    io.files
+        .borrow_mut()
+        .insert(PathBuf::from("big.txt"), "y".repeat(5000));
+    lox.interpreter.set_io(io);
+    lox.interpreter.set_sandbox(SandboxConfig {
+        max_string_bytes: Some(1000),
+        ..SandboxConfig::default()
+    });
+
+    //Natives whose result would pass the limit fail before building it
+    let s = "a".repeat(100);
+    let checks = [
+        format!("replace(\"{0}\", \"a\", \"{0}\")", s),
+        format!("join([\"{0}\", \"{0}\", \"{0}\", \"{0}\", \"{0}\"], \"{0}{0}\")", s),
+        format!("to_string(split(\"{0}{0}{0}{0}{0}\", \"\"))", s),
+        format!("upper(\"{0}{0}{0}{0}{0}{0}{0}{0}{0}{0}{0}\")", s),
+        "read_file(\"big.txt\")".to_string(),
+        "read_line()".to_string(),
+    ];
+    for check in &checks {
+        let source = format!("try {{ {}; }} catch (e) {{ print e.message; }}", check);
+        assert_eq!(lox.eval_source(&source), Ok(LoxValue::Nil));
+    }
+    //Results within the limit still work
+    let source = format!("print len(replace(\"{}\", \"a\", \"bb\"));", s);
+    assert_eq!(lox.eval_source(&source), Ok(LoxValue::Nil));
+    drop(lox);
+
+    let error = "Sandbox limit exceeded: more than 1000 bytes of strings.\n";
+    assert_eq!(String::from_utf8(buf).unwrap(), error.repeat(checks.len()) + "200\n");
+}
+#[test]
+fn test_register_native() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+
+    //Natives can capture host state
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    lox.interpreter.register_native("next_id", 0, move |_arguments, _interpreter| {
+        counter.set(counter.get() + 1);
+        Ok(LoxValue::Number(counter.get() as f64))
+    });
+    lox.interpreter.register_native("sum", Arity::AtLeast(1), |arguments, _interpreter| {
+        let mut total = 0.0;
+        for argument in arguments {
+            match argument {
+                LoxValue::Number(value) => total += value,
+                _ => return Err(RuntimeError::new("sum() expects numbers.".to_string(), 0)),
+            }
+        }
+        Ok(LoxValue::Number(total))
+    });
+    lox.interpreter.register_native("greet", Arity::Range(1, 2), |arguments, _interpreter| {
+        let greeting = match arguments.get(1) {
+            Some(greeting) => greeting.to_string(),
+            None => "Hello".to_string(),
+        };
+        Ok(LoxValue::LoxString(format!("{}, {}!", greeting, arguments[0])))
+    });
+
+    let source = "
+print next_id() + next_id();
+print sum(1, 2, 3, 4);
+print greet(\"Lox\");
+print greet(\"Lox\", \"Hi\");
+try { sum(1, \"2\"); } catch (e) { print e.message; print e.line; }";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+
+    match lox.eval_source("greet(1, 2, 3);") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Expected 1 to 2 arguments but got 3.")
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+    match lox.eval_source("sum();") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Expected at least 1 arguments but got 0.")
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+
+    //Errors from natives keep their kind, so a limit error raised by the host can't be caught by the script
+    lox.interpreter.register_native("stop", 0, |_arguments, _interpreter| {
+        Err(RuntimeError::execution_limit("Execution limit exceeded: stopped by host.".to_string()))
+    });
+    match lox.eval_source("try { stop(); } catch (e) { print \"caught\"; }") {
+        Err(LoxError::LimitExceeded(diagnostic)) => {
+            assert_eq!(diagnostic.message, "Execution limit exceeded: stopped by host.")
+        }
+        other => panic!("Expected limit error, got {:?}", other),
+    }
+    //Native errors without a position are placed at the call's closing parenthesis
+    match lox.eval_source("var x = 1;\nprint sum(x, nil);") {
+        Err(LoxError::Runtime(diagnostic)) => {
+            assert_eq!((diagnostic.line, diagnostic.column), (2, 17));
+            assert_eq!(diagnostic.trace, vec!["[line 2] in script"]);
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+    drop(lox);
+    assert_eq!(calls.get(), 2);
+    let expected = "3\n10\nHello, Lox!\nHi, Lox!\nsum() expects numbers.\n6\n";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+#[test]
+fn test_io_natives() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let io = Rc::new(MemoryIo::new(&["Ada", "3"]));
+    io.files
+        .borrow_mut()
+        .insert(PathBuf::from("data/in.txt"), "a,b\nc".to_string());
+    lox.interpreter.set_io(io.clone());
+
+    let source = "
+var name = input(\"Name? \");
+print \"Hello, \" + name;
+print read_line();
+print read_line();
+print read_file(\"data/in.txt\");
+write_file(\"data/out.txt\", \"one\");
+append_file(\"data/out.txt\", \", two\");
+append_file(\"log.txt\", \"new\");
+print file_exists(\"data/out.txt\");
+print file_exists(\"data\");
+print file_exists(\"missing.txt\");
+print list_dir(\"data\");
+print list_dir(\".\");
+try { read_file(\"missing.txt\"); } catch (e) { print e.message; }
+try { write_file(\"x.txt\", 1); } catch (e) { print e.message; }";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+
+    //A sandbox can deny all I/O
+    lox.interpreter.set_sandbox(SandboxConfig {
+        deny_io: true,
+        ..SandboxConfig::default()
+    });
+    let denied = "try { read_file(\"data/in.txt\"); } catch (e) { print e.message; }";
+    assert_eq!(lox.eval_source(denied), Ok(LoxValue::Nil));
+    drop(lox);
+
+    assert_eq!(io.prompts.borrow().as_str(), "Name? ");
+    assert_eq!(io.files.borrow()[&PathBuf::from("data/out.txt")], "one, two");
+    assert_eq!(io.files.borrow()[&PathBuf::from("log.txt")], "new");
+    let expected = "Hello, Ada
+3
+nil
+a,b
+c
+true
+true
+false
+[\"in.txt\", \"out.txt\"]
+[\"data\", \"log.txt\"]
+Could not read 'missing.txt': missing.txt does not exist.
+write_file() expects a string to write.
+Sandbox does not allow console or file access.
+";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+}
+#[test]
+fn test_file_natives_on_disk() {
+    let directory = std::env::temp_dir().join(format!("rusty_lox_io_{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("notes.txt");
+    let path = path.to_str().unwrap();
+
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let source = format!(
+        "write_file(\"{0}\", \"first\");
+append_file(\"{0}\", \" second\");
+print read_file(\"{0}\");
+print list_dir(\"{1}\");",
+        path,
+        directory.to_str().unwrap()
+    );
+    assert_eq!(lox.eval_source(&source), Ok(LoxValue::Nil));
+    drop(lox);
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), "first second\n[\"notes.txt\"]\n");
+}
+#[test]
+fn test_random_natives() {
+    let source = "
+seed(42);
+var first = [random(), random_int(1, 6), random_int(-5, 5)];
+seed(42);
+var second = [random(), random_int(1, 6), random_int(-5, 5)];
+print first[0] == second[0] and first[1] == second[1] and first[2] == second[2];
+
+var in_range = true;
+var seen = {};
+for (var i = 0; i < 500; i = i + 1) {
+    var x = random();
+    var n = random_int(1, 6);
+    if (x < 0 or x >= 1 or n < 1 or n > 6 or !is_integer(n)) in_range = false;
+    seen[n] = true;
+}
+print in_range;
+print len(seen);
+print random_int(7, 7);
+
+var deck = [1, 2, 3, 4, 5, 6, 7, 8];
+shuffle(deck);
+var total = 0;
+for (var i = 0; i < len(deck); i = i + 1) total = total + deck[i];
+print len(deck);
+print total;
+
+try { random_int(5, 1); } catch (e) { print e.message; }
+try { random_int(1.5, 2); } catch (e) { print e.message; }
+try { shuffle(\"abc\"); } catch (e) { print e.message; }
+try { seed(nil); } catch (e) { print e.message; }
+";
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    drop(lox);
+    let expected = "true
+true
+6
+7
+8
+36
+random_int() expects its lower bound not to be greater than its upper bound.
+random_int() expects two integers.
+shuffle() expects a list.
+seed() expects an integer.
+";
+    assert_eq!(String::from_utf8(buf).unwrap(), expected);
+
+    //Each interpreter has its own stream, and the host can seed it
+    let draw = "var xs = [random(), random(), random()]; xs;";
+    let mut first_buf = Vec::new();
+    let mut second_buf = Vec::new();
+    let mut first = Lox::new(&mut first_buf);
+    let mut second = Lox::new(&mut second_buf);
+    first.interpreter.set_random_seed(7);
+    second.interpreter.set_random_seed(7);
+    let expected = first.eval_source(draw).unwrap().to_string();
+    first.eval_source("random(); random();").unwrap();
+    assert_eq!(second.eval_source(draw).unwrap().to_string(), expected);
+    second.interpreter.set_random_seed(8);
+    assert_ne!(second.eval_source(draw).unwrap().to_string(), expected);
+}
+#[test]
+fn test_eval_source_reuses_memory() {
+    //Variables are resolved by the address of their expression. Later programs reuse the memory of earlier ones,
+    //so a global must not pick up the depth of a local that was freed
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    for _ in 0..50 {
+        let local = "{ var a = 1; { var b = a; { var c = b; print c; } } }";
+        assert_eq!(lox.eval_source(local), Ok(LoxValue::Nil));
+        assert!(lox.eval_source("clock; clock; clock;").is_ok());
+    }
+
+    //The resolutions of dropped programs are released, those of functions still alive are kept
+    assert!(lox.eval_source("fun f(x) { return x; }").is_ok());
+    let kept = lox.interpreter.resolved_locals();
+    for _ in 0..1000 {
+        assert!(lox.eval_source("{ var a = 1; print a; }").is_ok());
+    }
+    assert!(lox.interpreter.resolved_locals() <= kept + 1);
+    assert_eq!(lox.eval_source("f(3);"), Ok(LoxValue::Number(3.0)));
+}
+#[test]
+fn test_eval_source() {
+    let mut buf = BufWriter::new(Vec::new());
+    let mut lox = Lox::new(&mut buf);
+    assert_eq!(lox.eval_source("var a = 2;"), Ok(LoxValue::Nil));
+    assert_eq!(lox.eval_source("print a; a * 21;"), Ok(LoxValue::Number(42.0)));
+    drop(lox);
+    assert_eq!(buf.into_inner().unwrap(), b"2\n");
+}
+#[test]
+fn test_eval_source_errors() {
+    let mut buf = BufWriter::new(Vec::new());
+    let mut lox = Lox::new(&mut buf);
+
+    //Scan errors are all collected
+    match lox.eval_source("var a = @;\nvar b = #;") {
+        Err(LoxError::Scan(diagnostics)) => {
+            assert_eq!(diagnostics.len(), 2);
+            assert_eq!((diagnostics[1].line, diagnostics[1].column), (2, 9));
+        }
+        other => panic!("Expected scan error, got {:?}", other),
+    }
+
+    match lox.eval_source("print 1 +;") {
+        Err(LoxError::Parse(diagnostics)) => {
+            assert_eq!(diagnostics[0].location, "at ';'");
+            assert_eq!(diagnostics[0].column, 10);
+        }
+        other => panic!("Expected parse error, got {:?}", other),
+    }
+
+    match lox.eval_source("{ var a = 1; var a = 2; }") {
+        Err(LoxError::Resolve(diagnostics)) => assert_eq!(diagnostics[0].line, 1),
+        other => panic!("Expected resolve error, got {:?}", other),
+    }
+
+    match lox.eval_source("print 1;\n\"a\" - 1;") {
+        Err(error @ LoxError::Runtime(_)) => {
+            let diagnostic = &error.diagnostics()[0];
+            assert_eq!((diagnostic.line, diagnostic.column), (2, 5));
+            assert_eq!(error.exit_code(), 70);
+        }
+        other => panic!("Expected runtime error, got {:?}", other),
+    }
+
+    //The interpreter is still usable after an error
+    assert_eq!(lox.eval_source("1 + 1;"), Ok(LoxValue::Number(2.0)));
+}
+#[test]
+fn test_diagnostic_rendering() {
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut lox = Lox::new(&mut out);
+    lox.error_buffer = Box::new(&mut errors);
+
+    let source = "var a = 1;\nprint a + \"x\";";
+    let error = lox.eval_source(source).unwrap_err();
+    lox.report(&error, source);
+    drop(lox);
+
+    let expected = "error[E0400]: Operand types do not match\n --> line 2, column 9 at '+'\n  |\n2 | print a + \"x\";\n  |         ^\n[line 2] in script\n";
+    assert_eq!(String::from_utf8(errors).unwrap(), expected);
+}
+#[test]
+fn test_diagnostic_rendering_names() {
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut lox = Lox::new(&mut out);
+    lox.error_buffer = Box::new(&mut errors);
+
+    //Undefined variables and properties and bad superclasses point at the name involved
+    let sources = [
+        "print 1 + missing;",
+        "missing = 1;",
+        "fun f() { var a = 1; { print a + b; } }\nf();",
+        "class A {}\nprint A().field;",
+        "class A {}\nclass B < A { m() { return super.nope; } }\nB().m();",
+        "var NotClass = 1;\nclass B < NotClass {}",
+    ];
+    for source in sources {
+        let error = lox.eval_source(source).unwrap_err();
+        lox.report(&error, source);
+    }
+    drop(lox);
+
+    let expected = "error[E0400]: Undefined variable 'missing'.
+ --> line 1, column 11 at 'missing'
+  |
+1 | print 1 + missing;
+  |           ^^^^^^^
+[line 1] in script
+error[E0400]: Undefined variable 'missing'.
+ --> line 1, column 1 at 'missing'
+  |
+1 | missing = 1;
+  | ^^^^^^^
+[line 1] in script
+error[E0400]: Undefined variable 'b'.
+ --> line 1, column 34 at 'b'
+  |
+1 | fun f() { var a = 1; { print a + b; } }
+  |                                  ^
+[line 1] in f()
+[line 2] in script
+error[E0400]: Undefined property 'field'.
+ --> line 2, column 11 at 'field'
+  |
+2 | print A().field;
+  |           ^^^^^
+[line 2] in script
+error[E0400]: Undefined property 'nope'.
+ --> line 2, column 34 at 'nope'
+  |
+2 | class B < A { m() { return super.nope; } }
+  |                                  ^^^^
+[line 2] in m()
+[line 3] in script
+error[E0400]: Superclass must be a class.
+ --> line 2, column 11 at 'NotClass'
+  |
+2 | class B < NotClass {}
+  |           ^^^^^^^^
+[line 2] in script
+";
+    assert_eq!(String::from_utf8(errors).unwrap(), expected);
+}
+#[test]
+fn test_diagnostic_rendering_non_ascii() {
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut lox = Lox::new(&mut out);
+    lox.error_buffer = Box::new(&mut errors);
+
+    //Columns count characters, so the caret stays under the token after multi-byte text
+    let source = "var s = \"héllo wörld\"; print s + 1;";
+    let error = lox.eval_source(source).unwrap_err();
+    lox.report(&error, source);
+    let source = "var s = \"é\"; print \"wörld;";
+    let error = lox.eval_source(source).unwrap_err();
+    lox.report(&error, source);
+    drop(lox);
+
+    let expected = "error[E0400]: Operand types do not match
+ --> line 1, column 32 at '+'
+  |
+1 | var s = \"héllo wörld\"; print s + 1;
+  |                                ^
+[line 1] in script
+error[E0100]: Unterminated string.
+ --> line 1, column 20
+  |
+1 | var s = \"é\"; print \"wörld;
+  |                    ^^^^^^^
+  = note: The string is never closed with '\"' before the end of the file.
+";
+    assert_eq!(String::from_utf8(errors).unwrap(), expected);
+}
+#[test]
+fn test_repl() {
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut lox = Lox::new(&mut out);
+    lox.error_buffer = Box::new(&mut errors);
+
+    //Each entry is a line and whether it completes the input
+    let lines = [
+        ("", true),
+        ("1 + 2", true),
+        ("var greeting = \"hi\";", true),
+        ("greeting", true),
+        ("fun add(a, b) {", false),
+        ("", false),
+        ("  return a + b;", false),
+        ("}", true),
+        ("add(2,", false),
+        ("  3)", true),
+        ("print add(1, 1)", false),
+        (";", true),
+        ("var x = [1, 2] + 3;", true),
+        ("var multi = \"first", false),
+        ("second\";", true),
+        ("len(multi)", true),
+        ("var y = 1; y + 1;", true),
+        (") bad", true),
+    ];
+    for (line, complete) in lines {
+        assert_eq!(lox.repl_line(line), complete, "line {:?}", line);
+    }
+    drop(lox);
+
+    let expected = "3\nhi\n5\n2\n12\n2\n";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+    let errors = String::from_utf8(errors).unwrap();
+    assert!(errors.contains("Invalid operands."), "{}", errors);
+    assert!(errors.contains("Expect Expression"), "{}", errors);
+}
+#[test]
+fn test_stack_trace() {
+    let source = "fun isEven(n) {\n  if (n == 0) return nil + 1;\n  return isOdd(n - 1);\n}\nfun isOdd(n) {\n  return isEven(n - 1);\n}\nisEven(2);";
+    let expected = vec![
+        "[line 2] in isEven()",
+        "[line 6] in isOdd()",
+        "[line 3] in isEven()",
+        "[line 8] in script",
+    ];
+
+    //Both backends produce the same trace
+    for backend in [Backend::TreeWalk, Backend::Bytecode(Vm::new())] {
+        let mut buf = Vec::new();
+        let mut lox = Lox::new(&mut buf);
+        lox.backend = backend;
+        match lox.eval_source(source) {
+            Err(LoxError::Runtime(diagnostic)) => assert_eq!(diagnostic.trace, expected),
+            other => panic!("Expected runtime error, got {:?}", other),
+        }
+    }
+}
+#[test]
+fn test_resolver_collects_errors() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let source = "{\n  var a = 1;\n  var a = 2;\n  var b = b;\n}\nprint this;\nclass A < A {}";
+    match lox.eval_source(source) {
+        Err(LoxError::Resolve(diagnostics)) => {
+            let lines: Vec<u32> = diagnostics.iter().map(|d| d.line).collect();
+            assert_eq!(lines, vec![3, 4, 6, 7]);
+        }
+        other => panic!("Expected resolve errors, got {:?}", other),
+    }
+}
+#[test]
+fn test_resolver_return_errors() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let source = "return 1;\nclass A {\n  init() { return 2; }\n  m() { return 3; }\n}";
+    match lox.eval_source(source) {
+        Err(LoxError::Resolve(diagnostics)) => {
+            let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+            assert_eq!(
+                messages,
+                vec![
+                    "Can't return from top-level code.",
+                    "Can't return a value from an initializer."
+                ]
+            );
+        }
+        other => panic!("Expected resolve errors, got {:?}", other),
+    }
+
+    //A bare return is allowed in an initializer
+    let source = "class B { init() { this.x = 1; return; } }\nprint B().x;";
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+}
+#[test]
+fn test_warnings() {
+    let source = "fun f(a, _b) {\n  var x = 1;\n  var y = 2;\n  y = 3;\n  { var a = y; print a; }\n}\nf(1, 2);";
+
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    lox.warning_mode = WarningMode::Report;
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    let warnings: Vec<(&str, u32)> = lox.warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(warnings, vec![("W0002", 1), ("W0001", 2), ("W0004", 5)]);
+
+    lox.warning_mode = WarningMode::Deny;
+    match lox.eval_source(source) {
+        Err(LoxError::Resolve(diagnostics)) => assert_eq!(diagnostics.len(), 3),
+        other => panic!("Expected warnings as errors, got {:?}", other),
+    }
+    drop(lox);
+    //The program only ran the first time
+    assert_eq!(buf, b"3\n");
+}
+#[test]
+fn test_shadowing_global_warning() {
+    //Locals and parameters shadowing a global are reported, even if the global is declared further down
+    let source = "var a = 1;\n{ var a = 2; print a; }\nfun f(b) { return b; }\nprint f(a);\nvar b = 3;";
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    lox.warning_mode = WarningMode::Report;
+    assert_eq!(lox.eval_source(source), Ok(LoxValue::Nil));
+    let warnings: Vec<(&str, u32)> = lox.warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(warnings, vec![("W0004", 2), ("W0004", 3)]);
+
+    //Natives are not globals of the program, so locals may reuse their names
+    assert_eq!(lox.eval_source("{ var len = 1; print len; }"), Ok(LoxValue::Nil));
+    assert!(lox.warnings.is_empty());
+
+    //Globals defined by earlier programs, like earlier REPL lines, are shadowed too
+    assert_eq!(lox.eval_source("{ var b = 4; print b; }"), Ok(LoxValue::Nil));
+    let warnings: Vec<(&str, u32)> = lox.warnings.iter().map(|w| (w.code, w.line)).collect();
+    assert_eq!(warnings, vec![("W0004", 1)]);
+}
+#[test]
+fn test_break_outside_loop() {
+    let mut buf = Vec::new();
+    let mut lox = Lox::new(&mut buf);
+    let source = "break;\nwhile (true) {\n  fun f() { continue; }\n  break;\n}";
+    match lox.eval_source(source) {
+        Err(LoxError::Resolve(diagnostics)) => {
+            let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+            assert_eq!(
+                messages,
+                vec![
+                    "Can't use 'break' outside of a loop.",
+                    "Can't use 'continue' outside of a loop."
+                ]
+            );
+        }
+        other => panic!("Expected resolve errors, got {:?}", other),
+    }
+}